@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
+use super::sea_orm_active_enums::AccessAction;
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -11,7 +12,8 @@ pub struct Model {
     #[sea_orm(column_type = "Binary(16)")]
     pub file_id: Vec<u8>,
     pub date_time: DateTime,
-    pub successful: i8,
+    pub successful: bool,
+    pub action: AccessAction,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
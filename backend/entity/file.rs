@@ -14,12 +14,35 @@ pub struct Model {
     pub download_until: DateTime,
     #[sea_orm(column_type = "Binary(255)")]
     pub encrypted_metadata: Vec<u8>,
+    pub successful_downloads: i32,
+    pub max_downloads: i32,
+    #[sea_orm(column_type = "Binary(16)", nullable)]
+    pub reservation_id: Option<Vec<u8>>,
+    pub reserved_until: Option<DateTime>,
+    pub deletion_hash: Option<String>,
+    pub status_hash: Option<String>,
+    pub cipher: i16,
+    #[sea_orm(column_type = "Binary(32)", nullable)]
+    pub key_salt: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(255)", nullable)]
+    pub wrapped_key: Option<Vec<u8>>,
+    pub key_params: Option<String>,
+    pub client_encrypted: bool,
+    pub pepper_id: Option<i16>,
+    pub master_key_id: Option<i16>,
+    #[sea_orm(column_type = "Binary(255)", nullable)]
+    pub wrapped_storage_key: Option<Vec<u8>>,
+    pub failed_attempts: i32,
+    pub plaintext_size: Option<i64>,
+    pub ciphertext_size: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::access_log::Entity")]
     AccessLog,
+    #[sea_orm(has_many = "super::recipient::Entity")]
+    Recipient,
 }
 
 impl Related<super::access_log::Entity> for Entity {
@@ -28,4 +51,10 @@ impl Related<super::access_log::Entity> for Entity {
     }
 }
 
+impl Related<super::recipient::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Recipient.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
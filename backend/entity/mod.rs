@@ -4,5 +4,8 @@ pub mod prelude;
 
 pub mod access_log;
 pub mod file;
+pub mod recipient;
+pub mod revocation;
+pub mod sea_orm_active_enums;
 
 pub use prelude::*;
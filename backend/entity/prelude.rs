@@ -2,3 +2,5 @@
 
 pub use super::access_log::Entity as AccessLog;
 pub use super::file::Entity as File;
+pub use super::recipient::Entity as Recipient;
+pub use super::revocation::Entity as Revocation;
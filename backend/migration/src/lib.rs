@@ -1,12 +1,42 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20250114_200507_create_tables;
+mod m20261018_100000_add_successful_downloads;
+mod m20261018_110000_add_download_reservation;
+mod m20261018_120000_add_max_downloads;
+mod m20261018_130000_add_revocation;
+mod m20261018_140000_add_status_hash;
+mod m20261018_150000_add_access_log_action;
+mod m20261018_160000_add_cipher;
+mod m20261018_170000_add_wrapped_key;
+mod m20261018_180000_add_client_encrypted;
+mod m20261018_190000_add_recipient;
+mod m20261018_200000_add_pepper_id;
+mod m20261018_210000_add_storage_key;
+mod m20261018_220000_add_failed_attempts;
+mod m20261018_230000_add_file_sizes;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20250114_200507_create_tables::Migration)]
+        vec![
+            Box::new(m20250114_200507_create_tables::Migration),
+            Box::new(m20261018_100000_add_successful_downloads::Migration),
+            Box::new(m20261018_110000_add_download_reservation::Migration),
+            Box::new(m20261018_120000_add_max_downloads::Migration),
+            Box::new(m20261018_130000_add_revocation::Migration),
+            Box::new(m20261018_140000_add_status_hash::Migration),
+            Box::new(m20261018_150000_add_access_log_action::Migration),
+            Box::new(m20261018_160000_add_cipher::Migration),
+            Box::new(m20261018_170000_add_wrapped_key::Migration),
+            Box::new(m20261018_180000_add_client_encrypted::Migration),
+            Box::new(m20261018_190000_add_recipient::Migration),
+            Box::new(m20261018_200000_add_pepper_id::Migration),
+            Box::new(m20261018_210000_add_storage_key::Migration),
+            Box::new(m20261018_220000_add_failed_attempts::Migration),
+            Box::new(m20261018_230000_add_file_sizes::Migration),
+        ]
     }
 }
@@ -1,6 +1,6 @@
 use sea_orm_migration::{
     prelude::*,
-    schema::{blob, boolean, date_time, string, uuid},
+    schema::{binary_len, blob, boolean, date_time, string},
 };
 
 #[derive(DeriveMigrationName)]
@@ -14,7 +14,7 @@ impl MigrationTrait for Migration {
                 Table::create()
                     .table(File::Table)
                     .if_not_exists()
-                    .col(uuid(File::Id).not_null().primary_key())
+                    .col(binary_len(File::Id, 16).not_null().primary_key())
                     .col(string(File::Hash).not_null().unique_key())
                     .col(string(File::UploaderIp).not_null())
                     .col(date_time(File::UploadedAt).not_null())
@@ -29,23 +29,21 @@ impl MigrationTrait for Migration {
                 Table::create()
                     .table(AccessLog::Table)
                     .if_not_exists()
-                    .col(uuid(AccessLog::Id).not_null().primary_key())
+                    .col(binary_len(AccessLog::Id, 16).not_null().primary_key())
                     .col(string(AccessLog::Ip).not_null())
-                    .col(uuid(AccessLog::FileId).not_null())
+                    .col(binary_len(AccessLog::FileId, 16).not_null())
                     .col(date_time(AccessLog::DateTime).not_null())
                     .col(boolean(AccessLog::Successful).not_null())
-                    .to_owned(),
-            )
-            .await?;
-
-        manager
-            .create_foreign_key(
-                ForeignKey::create()
-                    .name("access_log_file")
-                    .from(AccessLog::Table, AccessLog::FileId)
-                    .to(File::Table, File::Id)
-                    .on_delete(ForeignKeyAction::Cascade)
-                    .on_update(ForeignKeyAction::Cascade)
+                    /* Created along with the table, as SQLite can't add
+                     * foreign keys to existing tables */
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("access_log_file")
+                            .from(AccessLog::Table, AccessLog::FileId)
+                            .to(File::Table, File::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
                     .to_owned(),
             )
             .await?;
@@ -55,17 +53,13 @@ impl MigrationTrait for Migration {
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
         manager
-            .drop_foreign_key(ForeignKey::drop().name("access_log_file").to_owned())
+            .drop_table(Table::drop().table(AccessLog::Table).to_owned())
             .await?;
 
         manager
             .drop_table(Table::drop().table(File::Table).to_owned())
             .await?;
 
-        manager
-            .drop_table(Table::drop().table(AccessLog::Table).to_owned())
-            .await?;
-
         Ok(())
     }
 }
@@ -0,0 +1,66 @@
+use sea_orm_migration::{prelude::*, schema::integer};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .add_column(integer(File::SuccessfulDownloads).not_null().default(0))
+                    .to_owned(),
+            )
+            .await?;
+
+        /* Count downloads that happened before this column existed */
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(File::Table)
+                    .value(File::SuccessfulDownloads, 1)
+                    .and_where(
+                        Expr::col(File::Id).in_subquery(
+                            Query::select()
+                                .column(AccessLog::FileId)
+                                .from(AccessLog::Table)
+                                .and_where(Expr::col(AccessLog::Successful).eq(true))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .drop_column(File::SuccessfulDownloads)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum File {
+    Table,
+    Id,
+    #[sea_orm(iden = "successful_downloads")]
+    SuccessfulDownloads,
+}
+
+#[derive(DeriveIden)]
+enum AccessLog {
+    Table,
+    #[sea_orm(iden = "file_id")]
+    FileId,
+    Successful,
+}
@@ -0,0 +1,69 @@
+use sea_orm_migration::{
+    prelude::*,
+    schema::{binary_len, date_time, string, string_null},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .add_column(string_null(File::DeletionHash))
+                    .to_owned(),
+            )
+            .await?;
+
+        /* Revocations are kept after their file has been removed, so there's
+         * no foreign key to the file table. */
+        manager
+            .create_table(
+                Table::create()
+                    .table(Revocation::Table)
+                    .if_not_exists()
+                    .col(binary_len(Revocation::Id, 16).not_null().primary_key())
+                    .col(binary_len(Revocation::FileId, 16).not_null())
+                    .col(string(Revocation::Ip).not_null())
+                    .col(date_time(Revocation::DateTime).not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Revocation::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .drop_column(File::DeletionHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum File {
+    Table,
+    #[sea_orm(iden = "deletion_hash")]
+    DeletionHash,
+}
+
+#[derive(DeriveIden)]
+enum Revocation {
+    Table,
+    Id,
+    #[sea_orm(iden = "file_id")]
+    FileId,
+    Ip,
+    #[sea_orm(iden = "date_time")]
+    DateTime,
+}
@@ -0,0 +1,40 @@
+use sea_orm_migration::{prelude::*, schema::string_len};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        /* All accesses before this column existed were downloads */
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccessLog::Table)
+                    .add_column(
+                        string_len(AccessLog::Action, 16)
+                            .not_null()
+                            .default("download"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AccessLog::Table)
+                    .drop_column(AccessLog::Action)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AccessLog {
+    Table,
+    Action,
+}
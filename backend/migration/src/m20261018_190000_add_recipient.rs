@@ -0,0 +1,68 @@
+use sea_orm_migration::{
+    prelude::*,
+    schema::{binary_len, binary_len_null, blob, date_time_null},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Recipient::Table)
+                    .if_not_exists()
+                    .col(binary_len(Recipient::Id, 16).not_null().primary_key())
+                    .col(binary_len(Recipient::FileId, 16).not_null())
+                    .col(binary_len(Recipient::PublicKey, 32).not_null())
+                    .col(binary_len(Recipient::EphemeralPublicKey, 32).not_null())
+                    .col(blob(Recipient::WrappedKey).not_null())
+                    .col(binary_len_null(Recipient::ChallengeSecret, 32))
+                    .col(date_time_null(Recipient::ChallengeUntil))
+                    /* Created along with the table, as SQLite can't add
+                     * foreign keys to existing tables */
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("recipient_file")
+                            .from(Recipient::Table, Recipient::FileId)
+                            .to(File::Table, File::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Recipient::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum File {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Recipient {
+    Table,
+    Id,
+    #[sea_orm(iden = "file_id")]
+    FileId,
+    #[sea_orm(iden = "public_key")]
+    PublicKey,
+    #[sea_orm(iden = "ephemeral_public_key")]
+    EphemeralPublicKey,
+    #[sea_orm(iden = "wrapped_key")]
+    WrappedKey,
+    #[sea_orm(iden = "challenge_secret")]
+    ChallengeSecret,
+    #[sea_orm(iden = "challenge_until")]
+    ChallengeUntil,
+}
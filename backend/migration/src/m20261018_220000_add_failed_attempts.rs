@@ -0,0 +1,137 @@
+use sea_orm_migration::{prelude::*, schema::integer};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .add_column(integer(File::FailedAttempts).not_null().default(0))
+                    .to_owned(),
+            )
+            .await?;
+
+        /* Count failed attempts that happened before this column existed */
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(File::Table)
+                    .value(
+                        File::FailedAttempts,
+                        SimpleExpr::SubQuery(
+                            None,
+                            Box::new(
+                                Query::select()
+                                    .expr(Expr::col(AccessLog::Id).count())
+                                    .from(AccessLog::Table)
+                                    .and_where(
+                                        Expr::col((AccessLog::Table, AccessLog::FileId))
+                                            .equals((File::Table, File::Id)),
+                                    )
+                                    .and_where(Expr::col(AccessLog::Successful).eq(false))
+                                    .to_owned()
+                                    .into_sub_query_statement(),
+                            ),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("file_download_until")
+                    .table(File::Table)
+                    .col(File::DownloadUntil)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("file_uploader_ip_uploaded_at")
+                    .table(File::Table)
+                    .col(File::UploaderIp)
+                    .col(File::UploadedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("access_log_file_id")
+                    .table(AccessLog::Table)
+                    .col(AccessLog::FileId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("access_log_file_id")
+                    .table(AccessLog::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("file_uploader_ip_uploaded_at")
+                    .table(File::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("file_download_until")
+                    .table(File::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .drop_column(File::FailedAttempts)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum File {
+    Table,
+    Id,
+    #[sea_orm(iden = "uploader_ip")]
+    UploaderIp,
+    #[sea_orm(iden = "uploaded_at")]
+    UploadedAt,
+    #[sea_orm(iden = "download_until")]
+    DownloadUntil,
+    #[sea_orm(iden = "failed_attempts")]
+    FailedAttempts,
+}
+
+#[derive(DeriveIden)]
+enum AccessLog {
+    Table,
+    Id,
+    #[sea_orm(iden = "file_id")]
+    FileId,
+    Successful,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::{prelude::*, schema::big_integer_null};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/* Sizes of files uploaded before are unknown, so both columns are nullable */
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .add_column(big_integer_null(File::PlaintextSize))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .add_column(big_integer_null(File::CiphertextSize))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .drop_column(File::CiphertextSize)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(File::Table)
+                    .drop_column(File::PlaintextSize)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum File {
+    Table,
+    #[sea_orm(iden = "plaintext_size")]
+    PlaintextSize,
+    #[sea_orm(iden = "ciphertext_size")]
+    CiphertextSize,
+}
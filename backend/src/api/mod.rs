@@ -1,11 +1,15 @@
 //! API module.
 //!
 //! This module contains the routes and server setup for the API. It includes
-//! submodules for configuration, download, and upload routes, as well as the
-//! server initialization.
+//! submodules for challenge, configuration, deletion, download, info, status,
+//! and upload routes, as well as the server initialization.
 mod routes {
+    pub mod challenge;
     pub mod configuration;
+    pub mod delete;
     pub mod download;
+    pub mod info;
+    pub mod status;
     pub mod upload;
 }
 mod server;
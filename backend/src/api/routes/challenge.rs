@@ -0,0 +1,104 @@
+use crate::database;
+use crate::recipient;
+use crate::request;
+use crate::return_logged;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::{http::StatusCode, Json};
+use base64::prelude::BASE64_URL_SAFE;
+use base64::Engine;
+use chrono::{TimeDelta, Utc};
+use entity::sea_orm_active_enums::AccessAction;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Time a challenge can be answered for
+const CHALLENGE_LIFETIME: TimeDelta = TimeDelta::seconds(60);
+
+/// A struct representing the request body for the challenge endpoint.
+///
+/// This struct is used to deserialize the JSON request body containing the
+/// X25519 public key of the recipient requesting a challenge.
+#[derive(Deserialize)]
+pub struct RequestBody {
+    pub public_key: String,
+}
+
+/// A struct representing the response for the challenge endpoint.
+///
+/// This struct is used to serialize the challenge to answer on download and
+/// the ephemeral public key that the encryption key has been wrapped with.
+#[derive(Serialize)]
+pub struct Response {
+    pub challenge: String,
+    pub ephemeral_public_key: String,
+}
+
+/// Handles the file challenge endpoint.
+///
+/// This function creates a new challenge for a recipient of the file, which
+/// has to be answered to download the file. A previous challenge of the
+/// recipient becomes invalid. Requests for unknown recipients count towards
+/// the max download tries of the file.
+pub async fn handler(
+    State(database_connection): State<DatabaseConnection>,
+    id: Path<Uuid>,
+    headers: HeaderMap,
+    body: Json<RequestBody>,
+) -> impl IntoResponse {
+    let request_ip = match request::get_request_ip(&headers) {
+        Ok(ip) => ip,
+        Err(error) => return_logged!(error, StatusCode::BAD_GATEWAY),
+    };
+
+    match database::get_downloadable_file(&database_connection, &id).await {
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Ok(Some(_)) => (),
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let recipient = match BASE64_URL_SAFE.decode(&body.public_key) {
+        Ok(public_key) => database::get_recipient(&database_connection, &id, &public_key).await,
+        Err(_) => Ok(None),
+    };
+
+    let recipient = match recipient {
+        Ok(Some(recipient)) => recipient,
+        Ok(None) => {
+            if let Err(error) = database::store_access_log(
+                &database_connection,
+                &request_ip,
+                &id,
+                AccessAction::Download,
+                false,
+            )
+            .await
+            {
+                return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
+            }
+
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let (challenge_secret, challenge) = recipient::create_challenge();
+
+    if let Err(error) = database::set_challenge(
+        &database_connection,
+        &recipient.id,
+        challenge_secret,
+        Utc::now() + CHALLENGE_LIFETIME,
+    )
+    .await
+    {
+        return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(Json(Response {
+        challenge: BASE64_URL_SAFE.encode(challenge),
+        ephemeral_public_key: BASE64_URL_SAFE.encode(&recipient.ephemeral_public_key),
+    }))
+}
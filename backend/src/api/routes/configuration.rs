@@ -14,6 +14,10 @@ pub struct Response {
     pub body_max_size: usize,
     #[serde(rename = "DaysFileAvailable")]
     pub default_days_lifetime: u64,
+    #[serde(rename = "MaxDaysFileAvailable")]
+    pub max_days_lifetime: u64,
+    #[serde(rename = "MaxDownloadsPerFile")]
+    pub max_downloads_per_file: u32,
 }
 
 /// Configuration endpoint.
@@ -24,6 +28,8 @@ pub async fn handler() -> impl IntoResponse {
     let response = Response {
         body_max_size: CONFIGURATION.body_max_size,
         default_days_lifetime: CONFIGURATION.days_file_available,
+        max_days_lifetime: CONFIGURATION.max_days_file_available,
+        max_downloads_per_file: CONFIGURATION.max_downloads_per_file,
     };
 
     Json(response)
@@ -0,0 +1,68 @@
+use crate::database;
+use crate::file;
+use crate::request;
+use crate::return_logged;
+use crate::util;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::{http::StatusCode, Json};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// A struct representing the request body for the delete endpoint.
+///
+/// This struct is used to deserialize the JSON request body containing the
+/// deletion token that has been returned on upload.
+#[derive(Deserialize)]
+pub struct RequestBody {
+    pub token: String,
+}
+
+/// Handles the file delete endpoint.
+///
+/// This function validates the deletion token, removes the file entry and its
+/// stored data and records the revocation. Files can be deleted as long as
+/// they haven't been removed yet, even if they can't be downloaded anymore.
+pub async fn handler(
+    State(database_connection): State<DatabaseConnection>,
+    id: Path<Uuid>,
+    headers: HeaderMap,
+    body: Json<RequestBody>,
+) -> impl IntoResponse {
+    let request_ip = match request::get_request_ip(&headers) {
+        Ok(ip) => ip,
+        Err(error) => return_logged!(error, StatusCode::BAD_GATEWAY),
+    };
+
+    let deletion_hash = match database::get_file(&database_connection, &id).await {
+        Ok(Some(file)) => file.deletion_hash,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    /* Files uploaded before deletion tokens existed can't be deleted */
+    let Some(deletion_hash) = deletion_hash else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    if util::get_validated_key(&body.token, &deletion_hash, None)
+        .await
+        .is_err()
+    {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match database::revoke_file(&database_connection, &request_ip, &id).await {
+        Ok(true) => (),
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+
+    if let Err(error) = file::delete(&id).await {
+        return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,34 +1,166 @@
+use crate::configuration::CONFIGURATION;
 use crate::database;
-use crate::encryption;
-use crate::encryption::Encoding;
-use crate::encryption::Encryption;
-use crate::error::Error;
+use crate::encryption::{CipherId, Context};
+use crate::error::{Error, Result};
 use crate::file;
+use crate::recipient;
 use crate::request;
 use crate::return_logged;
+use crate::shares;
 use crate::util;
+use axum::body::Body;
 use axum::extract::{Path, State};
-use axum::http::HeaderMap;
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{HeaderMap, HeaderValue};
 use axum::response::IntoResponse;
 use axum::{http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::AccessAction;
+use futures::{future, stream, StreamExt, TryStreamExt};
 use sea_orm::DatabaseConnection;
 use serde::Deserialize;
+use std::io::Error as IoError;
+use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 /// A struct representing the request body for the download endpoint.
 ///
 /// This struct is used to deserialize the JSON request body containing the
-/// key needed to decrypt the requested file.
+/// key needed to decrypt the requested file and its passphrase, if any.
+/// Recipients of a file send their public key and the response to their
+/// challenge instead, along with the shared secret of their wrapped key as
+/// `key`. Holders of shares of the key send a threshold of shares instead of
+/// the key.
 #[derive(Deserialize)]
 pub struct RequestBody {
+    #[serde(default)]
     pub key: String,
+    pub passphrase: Option<String>,
+    pub public_key: Option<String>,
+    pub response: Option<String>,
+    pub shares: Option<Vec<String>>,
+}
+
+/// Reservation of a file for a two-phase download
+struct Reservation {
+    /// Id of the reservation
+    id: Uuid,
+    /// Time of the last renewal of the reservation
+    renewed_at: DateTime<Utc>,
+}
+
+/// Guard tracking the transfer of a file while its body is being streamed.
+///
+/// On drop, the stored data of the file is deleted once the file can't be
+/// downloaded anymore. For two-phase downloads, the download is only finalized
+/// if the body has been streamed completely. Otherwise the reservation is
+/// released, so that the file can be downloaded again.
+struct DownloadGuard {
+    database_connection: DatabaseConnection,
+    ip: String,
+    id: Uuid,
+    reservation: Option<Reservation>,
+    completed: bool,
+}
+
+impl DownloadGuard {
+    /// Renews the reservation, if any, once half of its time has passed
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<()>`] if file is (still) reserved
+    /// * [`Err<Error>`] on error or if reservation has been lost
+    async fn renew_reservation(&mut self) -> Result<()> {
+        let Some(reservation) = &mut self.reservation else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        if now - reservation.renewed_at < CONFIGURATION.download_reservation_time / 2 {
+            return Ok(());
+        }
+
+        let renewed = database::renew_reservation(
+            &self.database_connection,
+            &self.id,
+            &reservation.id,
+            now + CONFIGURATION.download_reservation_time,
+        )
+        .await?;
+
+        if !renewed {
+            return Err(Error::DownloadReservationLost);
+        }
+
+        reservation.renewed_at = now;
+        Ok(())
+    }
+}
+
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        let database_connection = self.database_connection.clone();
+        let ip = std::mem::take(&mut self.ip);
+        let id = self.id;
+        let reservation = self.reservation.take();
+        let completed = self.completed;
+
+        tokio::spawn(async move {
+            let downloaded = match reservation {
+                None => true,
+                Some(reservation) if completed => {
+                    match database::finalize_download(
+                        &database_connection,
+                        &ip,
+                        &id,
+                        &reservation.id,
+                    )
+                    .await
+                    {
+                        Ok(finalized) => finalized,
+                        Err(error) => {
+                            log::error!("Could not finalize download of file {id}: {error:?}");
+                            false
+                        }
+                    }
+                }
+                Some(reservation) => {
+                    if let Err(error) =
+                        database::release_download(&database_connection, &id, &reservation.id).await
+                    {
+                        log::error!("Could not release reservation of file {id}: {error:?}");
+                    }
+                    false
+                }
+            };
+
+            if !downloaded {
+                return;
+            }
+
+            /* Files that may be downloaded multiple times are kept until
+             * their last allowed download */
+            match database::get_downloadable_file(&database_connection, &id).await {
+                Ok(None) => (),
+                Ok(Some(_)) => return,
+                Err(error) => {
+                    log::error!("Could not check whether file {id} is used: {error:?}");
+                    return;
+                }
+            }
+
+            if let Err(error) = file::delete(&id).await {
+                log::error!("Could not delete used file {id}: {error:?}");
+            }
+        });
+    }
 }
 
 /// Handles the file download endpoint.
 ///
 /// This function processes the download request, validates the key, logs the
-/// access, decrypts the file, and returns the file content along with the
-/// appropriate headers.
+/// access, and returns the file content along with the appropriate headers.
+/// The file content is decrypted and streamed segment by segment.
 pub async fn handler(
     State(database_connection): State<DatabaseConnection>,
     id: Path<Uuid>,
@@ -46,9 +178,52 @@ pub async fn handler(
         Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
     };
 
-    let Ok(key) = util::get_validated_key(&body.key, &file.hash) else {
-        if let Err(error) =
-            database::store_access_log(&database_connection, &request_ip, &id, false).await
+    let cipher = match CipherId::try_from(file.cipher) {
+        Ok(cipher) => cipher,
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    /* Holders of shares send them instead of the key */
+    let encoded_key = match body.shares.as_deref() {
+        None => Some(body.key.clone()),
+        Some(encoded_shares) => shares::combine(encoded_shares).ok(),
+    };
+
+    let key = match body.public_key.as_deref() {
+        None => match encoded_key.as_deref() {
+            None => Err(Error::KeyInvalid),
+            Some(encoded_key) => {
+                match util::get_validated_key(encoded_key, &file.hash, file.pepper_id).await {
+                    Ok(key) => {
+                        file::get_encryption_key(&file, key, body.passphrase.as_deref(), cipher)
+                            .await
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+        },
+        Some(public_key) => {
+            recipient::get_validated_key(
+                &database_connection,
+                &file,
+                public_key,
+                body.response.as_deref(),
+                &body.key,
+                cipher,
+            )
+            .await
+        }
+    };
+
+    let Ok(key) = key else {
+        if let Err(error) = database::store_access_log(
+            &database_connection,
+            &request_ip,
+            &id,
+            AccessAction::Download,
+            false,
+        )
+        .await
         {
             return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
         }
@@ -56,33 +231,134 @@ pub async fn handler(
         return Err(StatusCode::UNAUTHORIZED);
     };
 
-    if let Err(error) =
-        database::store_access_log(&database_connection, &request_ip, &id, true).await
-    {
-        return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR)
+    /* Hashes created with weaker parameters are replaced transparently */
+    if let (None, Some(encoded_key)) = (&body.public_key, &encoded_key) {
+        if let Some((hash, pepper_id)) =
+            util::rehash_key(encoded_key, &file.hash, file.pepper_id).await
+        {
+            if let Err(error) =
+                database::update_hash(&database_connection, &id, hash, pepper_id).await
+            {
+                log::error!("Could not update hash of file {}: {error:?}", *id);
+            }
+        }
     }
 
-    let content = match file::load_data(&id)
-        .and_then(encryption::Data::decode)
-        .and_then(|data| data.decrypt(&key))
-    {
+    /* Client-encrypted files are served as stored, since the server doesn't
+     * know their key. In that case, `key` is just the verified token. */
+    let content = match file::load_data(&file).await.and_then(|data| {
+        if file.client_encrypted {
+            Ok(ReaderStream::new(data)
+                .map_ok(|chunk| chunk.to_vec())
+                .map_err(Error::LoadingFileFailed)
+                .boxed())
+        } else {
+            cipher.decrypt_stream(data, &key, Context::content(*id))
+        }
+    }) {
         Ok(content) => content,
         Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
     };
 
-    let response_headers = match encryption::Data::decode(file.encrypted_metadata)
-        .and_then(|data| data.decrypt(&key))
-        .and_then(|data| String::from_utf8(data).map_err(|_| Error::DecryptionFailed))
-        .and_then(|json| {
-            serde_json::from_str::<file::Metadata>(&json).map_err(Error::JsonSerializationFailed)
-        }) {
-        Ok(metadata) => metadata.into(),
-        _ => HeaderMap::new(),
+    let (first_segment, content) = content.into_future().await;
+
+    /* Authenticate first segment before claiming and responding, so that
+     * errors regarding the key or the stored data neither use up a download
+     * nor go unreported by status code. */
+    let first_segment = match first_segment {
+        Some(Ok(segment)) => segment,
+        Some(Err(error)) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+        None => vec![],
+    };
+
+    let reservation = if CONFIGURATION.two_phase_download {
+        let reservation_id = Uuid::new_v4();
+        let renewed_at = Utc::now();
+
+        match database::reserve_download(
+            &database_connection,
+            &id,
+            &reservation_id,
+            renewed_at + CONFIGURATION.download_reservation_time,
+        )
+        .await
+        {
+            Ok(true) => Some(Reservation {
+                id: reservation_id,
+                renewed_at,
+            }),
+            Ok(false) => return Err(unavailable_status(&database_connection, &id).await),
+            Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    } else {
+        match database::claim_download(&database_connection, &request_ip, &id).await {
+            Ok(true) => None,
+            Ok(false) => return Err(unavailable_status(&database_connection, &id).await),
+            Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+        }
     };
 
-    if let Err(error) = file::delete(&id) {
-        log::error!("Could not delete used file {}: {error:?}", id.to_string());
+    /* Created right after claiming / reserving, so that a reservation is
+     * released on every following error. */
+    let guard = DownloadGuard {
+        database_connection,
+        ip: request_ip,
+        id: *id,
+        reservation,
+        completed: false,
+    };
+
+    let mut response_headers =
+        match file::Metadata::decrypt(file.encrypted_metadata, &key, &id, cipher) {
+            Ok(metadata) => metadata.into(),
+            /* Client-encrypted files are stored without metadata */
+            _ => HeaderMap::new(),
+        };
+
+    /* Sizes of files uploaded before sizes were recorded are unknown */
+    if let Some(size) = file.plaintext_size {
+        response_headers.insert(CONTENT_LENGTH, HeaderValue::from(size));
     }
 
-    Ok((response_headers, content))
+    /* The guard is dropped along with the body, so as soon as the body has
+     * been streamed or the client disconnected. */
+    let content = stream::once(future::ready(Ok(first_segment))).chain(content);
+
+    let body = stream::unfold((content, guard), |(mut content, mut guard)| async move {
+        let segment = match content.next().await {
+            Some(Ok(segment)) => guard.renew_reservation().await.map(|_| segment),
+            Some(Err(error)) => Err(error),
+            None => {
+                guard.completed = true;
+                drop(guard);
+                return None;
+            }
+        };
+
+        Some((segment, (content, guard)))
+    })
+    .map_err(move |error| {
+        log::error!("Could not stream file {}: {error:?}", id.to_string());
+        IoError::other("Streaming file failed")
+    });
+
+    Ok((response_headers, Body::from_stream(body)))
+}
+
+/// Returns status code for a file that could not be claimed or reserved.
+///
+/// # Returns
+///
+/// * [`StatusCode::CONFLICT`] if file is currently being downloaded
+/// * [`StatusCode::NOT_FOUND`] if file isn't downloadable (anymore)
+/// * [`StatusCode::INTERNAL_SERVER_ERROR`] on error
+async fn unavailable_status(database_connection: &DatabaseConnection, id: &Uuid) -> StatusCode {
+    match database::get_downloadable_file(database_connection, id).await {
+        Ok(Some(_)) => StatusCode::CONFLICT,
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(error) => {
+            log::error!("{error:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
 }
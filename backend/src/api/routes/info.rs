@@ -0,0 +1,149 @@
+use crate::database;
+use crate::encryption::CipherId;
+use crate::error::Error;
+use crate::file;
+use crate::recipient;
+use crate::request;
+use crate::return_logged;
+use crate::shares;
+use crate::util;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::{http::StatusCode, Json};
+use entity::sea_orm_active_enums::AccessAction;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A struct representing the request body for the info endpoint.
+///
+/// This struct is used to deserialize the JSON request body containing the
+/// key needed to decrypt the metadata of the requested file and its
+/// passphrase, if any. Recipients of a file send their public key and the
+/// response to their challenge instead, see [`super::download::RequestBody`].
+/// Holders of shares of the key send their shares instead of the key.
+#[derive(Deserialize)]
+pub struct RequestBody {
+    #[serde(default)]
+    pub key: String,
+    pub passphrase: Option<String>,
+    pub public_key: Option<String>,
+    pub response: Option<String>,
+    pub shares: Option<Vec<String>>,
+}
+
+/// A struct representing the response for the info endpoint.
+///
+/// This struct is used to serialize the decrypted metadata of the file along
+/// with its size in bytes, which is unknown for files uploaded before sizes
+/// were recorded.
+#[derive(Serialize)]
+pub struct Response {
+    #[serde(flatten)]
+    pub metadata: file::Metadata,
+    pub size: Option<u64>,
+}
+
+/// Handles the file info endpoint.
+///
+/// This function validates the key and returns the decrypted metadata of the
+/// file, without downloading it. Every access is logged, and failed attempts
+/// count towards the max download tries of the file, just like failed
+/// downloads do.
+pub async fn handler(
+    State(database_connection): State<DatabaseConnection>,
+    id: Path<Uuid>,
+    headers: HeaderMap,
+    body: Json<RequestBody>,
+) -> impl IntoResponse {
+    let request_ip = match request::get_request_ip(&headers) {
+        Ok(ip) => ip,
+        Err(error) => return_logged!(error, StatusCode::BAD_GATEWAY),
+    };
+
+    let file = match database::get_downloadable_file(&database_connection, &id).await {
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Ok(Some(file)) => file,
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let cipher = match CipherId::try_from(file.cipher) {
+        Ok(cipher) => cipher,
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    /* Holders of shares send them instead of the key */
+    let encoded_key = match body.shares.as_deref() {
+        None => Some(body.key.clone()),
+        Some(encoded_shares) => shares::combine(encoded_shares).ok(),
+    };
+
+    let key = match body.public_key.as_deref() {
+        None => match encoded_key.as_deref() {
+            None => Err(Error::KeyInvalid),
+            Some(encoded_key) => {
+                match util::get_validated_key(encoded_key, &file.hash, file.pepper_id).await {
+                    Ok(key) => {
+                        file::get_encryption_key(&file, key, body.passphrase.as_deref(), cipher)
+                            .await
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+        },
+        Some(public_key) => {
+            recipient::get_validated_key(
+                &database_connection,
+                &file,
+                public_key,
+                body.response.as_deref(),
+                &body.key,
+                cipher,
+            )
+            .await
+        }
+    };
+
+    if let Err(error) = database::store_access_log(
+        &database_connection,
+        &request_ip,
+        &id,
+        AccessAction::Info,
+        key.is_ok(),
+    )
+    .await
+    {
+        return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let Ok(key) = key else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    /* Hashes created with weaker parameters are replaced transparently */
+    if let (None, Some(encoded_key)) = (&body.public_key, &encoded_key) {
+        if let Some((hash, pepper_id)) =
+            util::rehash_key(encoded_key, &file.hash, file.pepper_id).await
+        {
+            if let Err(error) =
+                database::update_hash(&database_connection, &id, hash, pepper_id).await
+            {
+                log::error!("Could not update hash of file {}: {error:?}", *id);
+            }
+        }
+    }
+
+    /* Client-encrypted files are stored without metadata */
+    if file.client_encrypted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match file::Metadata::decrypt(file.encrypted_metadata, &key, &id, cipher) {
+        Ok(metadata) => Ok(Json(Response {
+            metadata,
+            size: file.plaintext_size.and_then(|size| size.try_into().ok()),
+        })),
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
@@ -0,0 +1,78 @@
+use crate::configuration::CONFIGURATION;
+use crate::database;
+use crate::request;
+use crate::return_logged;
+use crate::util;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::{http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A struct representing the response for the status endpoint.
+///
+/// This struct is used to serialize the delivery status of a file. It doesn't
+/// contain any Ips of clients that accessed the file.
+#[derive(Serialize)]
+pub struct Response {
+    pub download_until: DateTime<Utc>,
+    pub downloaded: bool,
+    pub last_downloaded_at: Option<DateTime<Utc>>,
+    pub successful_downloads: u32,
+    pub max_downloads: u32,
+    pub failed_attempts: u32,
+    pub max_failed_attempts: u32,
+    pub size: Option<u64>,
+}
+
+/// Handles the file status endpoint.
+///
+/// This function validates the status token, given as bearer token in the
+/// `Authorization` header, and returns the delivery status of the file. The
+/// status token doesn't allow downloading the file.
+pub async fn handler(
+    State(database_connection): State<DatabaseConnection>,
+    id: Path<Uuid>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(token) = request::get_bearer_token(&headers) else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let file = match database::get_file(&database_connection, &id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    /* Files uploaded before status tokens existed have no status */
+    let Some(status_hash) = &file.status_hash else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    if util::get_validated_key(token, status_hash, None)
+        .await
+        .is_err()
+    {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let summary = match database::get_access_summary(&database_connection, &file).await {
+        Ok(summary) => summary,
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    Ok(Json(Response {
+        download_until: file.download_until.and_utc(),
+        downloaded: summary.successful_downloads > 0,
+        last_downloaded_at: summary.last_downloaded_at,
+        successful_downloads: summary.successful_downloads,
+        max_downloads: file.max_downloads.try_into().unwrap_or_default(),
+        failed_attempts: summary.failed_attempts,
+        max_failed_attempts: CONFIGURATION.max_download_tries,
+        size: file.plaintext_size.and_then(|size| size.try_into().ok()),
+    }))
+}
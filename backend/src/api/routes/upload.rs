@@ -1,40 +1,126 @@
 use crate::configuration::CONFIGURATION;
-use crate::encryption::{Encoding, Encryption};
+use crate::database;
+use crate::encryption::master_key::WrappedStorageKey;
+use crate::encryption::{CipherId, Context};
 use crate::error::Error;
 use crate::file;
-use crate::hash::{Hash, Hashing};
+use crate::hash::{pepper, Hash, Hashing};
+use crate::recipient::RecipientKey;
 use crate::request;
 use crate::return_logged;
-use crate::{database, encryption};
-use axum::extract::State;
+use crate::shares;
+use crate::util;
+use axum::body::Bytes;
+use axum::extract::{Query, State};
 use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::{extract::Request, http::StatusCode, Json};
 use base64::prelude::BASE64_URL_SAFE;
 use base64::Engine;
-use futures::{StreamExt, TryStreamExt};
+use chrono::TimeDelta;
+use futures::{future, Stream, TryStreamExt};
 use sea_orm::DatabaseConnection;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::io::{Error as IoError, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::AsyncRead;
 use tokio_util::io::StreamReader;
 use uuid::Uuid;
 
 // A struct representing the response for the upload endpoint.
 ///
-/// This struct is used to serialize the response containing the file id and
-/// the encryption key.
+/// This struct is used to serialize the response containing the file id, the
+/// encryption key and the tokens to delete the file again or to query its
+/// status. Client-encrypted files have no key that the server knows of. If the
+/// key has been split, the shares are returned instead of the key.
 #[derive(Serialize)]
 pub struct Response {
     pub id: String,
-    pub key: String,
+    pub key: Option<String>,
+    pub shares: Option<Vec<String>>,
+    pub deletion_token: String,
+    pub status_token: String,
+}
+
+/// A struct representing the query options of the upload endpoint.
+///
+/// Options that are not given fall back to the server defaults. Given options
+/// must not exceed the maximums of [`CONFIGURATION`].
+#[derive(Deserialize)]
+pub struct Options {
+    /// Seconds until the file expires
+    pub expires_in: Option<u32>,
+    /// Number of times the file can be downloaded
+    pub max_downloads: Option<u32>,
+    /// Whether the body has already been encrypted by the client and must be
+    /// stored as is
+    #[serde(default)]
+    pub client_encrypted: bool,
+    /// Number of shares to split the key into
+    pub shares: Option<u8>,
+    /// Number of shares required to reconstruct the key
+    pub threshold: Option<u8>,
+}
+
+/// Secret that grants access to an uploaded file, which is stored hashed
+enum AccessSecret {
+    /// Key that still has to be hashed
+    Key(Vec<u8>),
+    /// Verifier hash supplied by the client, which is stored as is
+    VerifierHash(String),
+}
+
+/// Hashes of the secrets of an uploaded file, see [`hash_secrets`]
+struct Hashes {
+    /// Hash of the key that grants access to the file
+    hash: String,
+    /// Id of the pepper mixed into `hash`, if any
+    pepper_id: Option<i16>,
+    /// Hash of the deletion token
+    deletion_hash: String,
+    /// Hash of the status token
+    status_hash: String,
+}
+
+/// Content of an uploaded file, as it has been stored
+struct StoredContent {
+    /// Secret that grants access to the file
+    access_secret: AccessSecret,
+    /// Key to hand out to the uploader, unless encrypted by the client
+    key: Option<Vec<u8>>,
+    /// File metadata in encrypted form
+    encrypted_metadata: Vec<u8>,
+    /// Cipher the file has been encrypted with
+    cipher: CipherId,
+    /// Salt of the key derived from the passphrase, if any
+    key_salt: Option<Vec<u8>>,
+    /// Encryption key wrapped with the passphrase, if any
+    wrapped_key: Option<Vec<u8>>,
+    /// Encoded parameters the key has been derived from the passphrase with,
+    /// if any
+    key_params: Option<String>,
+    /// Encryption keys wrapped for the recipients, if any
+    recipients: Vec<RecipientKey>,
+    /// Wrapped storage key, if content has been encrypted with one
+    storage_key: Option<WrappedStorageKey>,
+    /// Size of the uploaded content in bytes, as served on download
+    plaintext_size: u64,
+    /// Size of the stored, encrypted content in bytes
+    ciphertext_size: u64,
 }
 
 /// Handles the file upload endpoint.
 ///
 /// This function processes the upload request, validates the request, stores
-/// the file, and returns the file id and encryption key.
+/// the file, and returns the file id and encryption key. If a passphrase is
+/// given, the returned key only grants access along with that passphrase. If
+/// recipients are given, no key is returned, as only the recipients can access
+/// the file. If shares are requested, the key is split into shares, of which
+/// the threshold is required to access the file.
 pub async fn handler(
     State(database_connection): State<DatabaseConnection>,
+    Query(options): Query<Options>,
     headers: HeaderMap,
     request: Request,
 ) -> impl IntoResponse {
@@ -43,33 +129,152 @@ pub async fn handler(
         Err(error) => return_logged!(error, StatusCode::BAD_GATEWAY),
     };
 
+    let lifetime = match options.expires_in {
+        None => CONFIGURATION.file_lifetime,
+        Some(0) => return Err(StatusCode::BAD_REQUEST),
+        Some(seconds) => TimeDelta::seconds(seconds.into()),
+    };
+
+    let max_downloads = options.max_downloads.unwrap_or(1);
+
+    if lifetime > CONFIGURATION.max_file_lifetime
+        || max_downloads == 0
+        || max_downloads > CONFIGURATION.max_downloads_per_file
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let split = match (options.threshold, options.shares) {
+        (None, None) => None,
+        (Some(threshold), Some(count))
+            if shares::is_valid(threshold, count)
+                && !options.client_encrypted
+                && request::get_recipients(&headers).is_ok_and(|keys| keys.is_empty()) =>
+        {
+            Some((threshold, count))
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
     match database::is_upload_limit_reached(&database_connection, &request_ip).await {
         Ok(false) => (),
         Ok(true) => return Err(StatusCode::TOO_MANY_REQUESTS),
         Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
     }
 
-    let content = match extract_body(request).await {
-        Ok(content) => content,
-        Err(_) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+    let id = Uuid::new_v4();
+
+    let stored = if options.client_encrypted {
+        store_client_encrypted(&id, &headers, request).await?
+    } else {
+        store_encrypted(&id, headers, request).await?
     };
 
-    let (encryption_data, key) = match encryption::Data::encrypt(content) {
-        Ok(result) => result,
+    let deletion_token = util::generate_token();
+    let status_token = util::generate_token();
+
+    let hashes = match hash_secrets(
+        stored.access_secret,
+        deletion_token.clone(),
+        status_token.clone(),
+    )
+    .await
+    {
+        Ok(hashes) => hashes,
         Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
     };
 
-    let id = Uuid::new_v4();
+    let new_file = database::NewFile {
+        id,
+        hash: hashes.hash,
+        pepper_id: hashes.pepper_id,
+        deletion_hash: hashes.deletion_hash,
+        status_hash: hashes.status_hash,
+        uploader_ip: request_ip,
+        encrypted_metadata: stored.encrypted_metadata,
+        lifetime,
+        max_downloads,
+        cipher: stored.cipher,
+        key_salt: stored.key_salt,
+        wrapped_key: stored.wrapped_key,
+        key_params: stored.key_params,
+        client_encrypted: options.client_encrypted,
+        recipients: stored.recipients,
+        storage_key: stored.storage_key,
+        plaintext_size: stored.plaintext_size,
+        ciphertext_size: stored.ciphertext_size,
+    };
 
-    if let Err(error) = file::store_data(&id, encryption_data.encode()) {
+    if let Err(error) = database::store_file(&database_connection, new_file).await {
         return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
     };
 
+    let (key, shares) = match (stored.key, split) {
+        (Some(key), Some((threshold, count))) => {
+            (None, Some(shares::split(&key, threshold, count)))
+        }
+        (key, _) => (key, None),
+    };
+
+    let encode = |data: Vec<u8>| BASE64_URL_SAFE.encode(data);
+
+    Ok(Json(Response {
+        id: id.into(),
+        key: key.map(encode),
+        shares: shares.map(|shares| shares.into_iter().map(encode).collect()),
+        deletion_token: BASE64_URL_SAFE.encode(&deletion_token),
+        status_token: BASE64_URL_SAFE.encode(&status_token),
+    }))
+}
+
+/// Encrypts the body of given `request` and stores it along with the file
+/// metadata of `headers`.
+///
+/// # Arguments
+///
+/// * `id` - File id
+/// * `headers` - Request headers, containing metadata, passphrase and
+///   recipients
+/// * `request` - Request to store body of
+///
+/// # Returns
+///
+/// * [`Ok<StoredContent>`] on success
+/// * [`Err<StatusCode>`] on error
+async fn store_encrypted(
+    id: &Uuid,
+    headers: HeaderMap,
+    request: Request,
+) -> Result<StoredContent, StatusCode> {
+    let passphrase = request::get_passphrase(&headers);
+
+    let Ok(public_keys) = request::get_recipients(&headers) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    if passphrase.is_some() && !public_keys.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let cipher = CONFIGURATION.cipher;
+    let body_size = Arc::new(AtomicU64::new(0));
+    let (encrypted_content, key) = cipher.encrypt_stream(
+        body_reader(request, Arc::clone(&body_size)),
+        Context::content(*id),
+    );
+
+    let Ok(recipients) = public_keys
+        .iter()
+        .map(|public_key| RecipientKey::wrap(&key, public_key, id, cipher))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
     let encrypted_metadata =
         match serde_json::to_string(&std::convert::Into::<file::Metadata>::into(headers))
             .map_err(Error::JsonSerializationFailed)
-            .and_then(|json| encryption::Data::encrypt_with_key(json.bytes(), &key))
-            .map(encryption::definitions::Encoding::encode)
+            .and_then(|json| cipher.encrypt_with_key(json.bytes(), &key, &Context::metadata(*id)))
         {
             Ok(metadata) => metadata,
             Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
@@ -79,51 +284,216 @@ pub async fn handler(
         return Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
     }
 
-    let hash = match Hash::hash(&key) {
-        Ok(hash) => hash,
-        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+    let stored = store_content(id, encrypted_content, cipher).await?;
+
+    let (key, key_salt, wrapped_key, key_params) = match passphrase {
+        None => (key, None, None, None),
+        Some(passphrase) => match file::WrappedKey::wrap(&key, &passphrase, id, cipher).await {
+            Ok(wrapped) => (
+                wrapped.link_key,
+                Some(wrapped.salt),
+                Some(wrapped.wrapped_key),
+                Some(wrapped.params),
+            ),
+            Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
+        },
     };
 
-    if let Err(error) = database::store_file(
-        &database_connection,
-        &id,
-        hash,
-        request_ip,
+    /* Files of recipients can't be accessed by key, so the hash of a key that
+     * is never handed out is stored instead. */
+    let key = recipients.is_empty().then_some(key);
+    let access_secret = AccessSecret::Key(key.clone().unwrap_or_else(util::generate_token));
+
+    Ok(StoredContent {
+        access_secret,
+        key,
         encrypted_metadata,
-    )
-    .await
+        cipher,
+        key_salt,
+        wrapped_key,
+        key_params,
+        recipients,
+        storage_key: stored.storage_key,
+        plaintext_size: body_size.load(Ordering::Relaxed),
+        ciphertext_size: stored.size,
+    })
+}
+
+/// Stores the body of given `request`, which has already been encrypted by
+/// the client, as is.
+///
+/// The server never learns the key of the file. Instead, the client supplies
+/// a verifier hash of the token that it sends to download the file. No
+/// metadata is stored, as it would be readable by the server.
+///
+/// # Arguments
+///
+/// * `id` - File id
+/// * `headers` - Request headers, containing the verifier hash
+/// * `request` - Request to store body of
+///
+/// # Returns
+///
+/// * [`Ok<StoredContent>`] on success
+/// * [`Err<StatusCode>`] on error
+async fn store_client_encrypted(
+    id: &Uuid,
+    headers: &HeaderMap,
+    request: Request,
+) -> Result<StoredContent, StatusCode> {
+    if request::get_passphrase(headers).is_some()
+        || !request::get_recipients(headers).is_ok_and(|public_keys| public_keys.is_empty())
     {
-        return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(hash) = request::get_verifier_hash(headers).filter(|hash| Hash::is_acceptable(hash))
+    else {
+        return Err(StatusCode::BAD_REQUEST);
     };
 
-    Ok(Json(Response {
-        id: id.into(),
-        key: BASE64_URL_SAFE.encode(&key),
-    }))
-}
+    let body_size = Arc::new(AtomicU64::new(0));
+    let content = body_stream(request, Arc::clone(&body_size))
+        .map_ok(|chunk| chunk.to_vec())
+        .map_err(Error::ReadingDataFailed);
 
-async fn extract_body(request: Request) -> Result<Vec<u8>, IoError> {
-    let mut body = vec![];
+    let cipher = CipherId::default();
+    let stored = store_content(id, content, cipher).await?;
 
-    let body_data_stream = request
-        .into_body()
-        .into_data_stream()
-        /* Add one byte to max size for range check later. If this byte is filled,
-         * we know that the body is too large. */
-        .take(CONFIGURATION.body_max_size + 1)
-        .map_err(|err| IoError::new(ErrorKind::Other, err));
+    Ok(StoredContent {
+        access_secret: AccessSecret::VerifierHash(hash),
+        key: None,
+        encrypted_metadata: vec![],
+        cipher,
+        key_salt: None,
+        wrapped_key: None,
+        key_params: None,
+        recipients: vec![],
+        storage_key: stored.storage_key,
+        plaintext_size: body_size.load(Ordering::Relaxed),
+        ciphertext_size: stored.size,
+    })
+}
+
+/// Hashes given `access_secret` and tokens of an uploaded file
+///
+/// Hashing is CPU-bound, so all hashes are created in one go on a blocking
+/// thread instead of blocking the runtime. The current pepper is only mixed
+/// into the hash of the key.
+///
+/// # Arguments
+///
+/// * `access_secret` - Secret that grants access to the file
+/// * `deletion_token` - Token to delete the file
+/// * `status_token` - Token to query the status of the file
+///
+/// # Returns
+///
+/// * [`Ok<Hashes>`] on success
+/// * [`Err<Error>`] on error
+async fn hash_secrets(
+    access_secret: AccessSecret,
+    deletion_token: Vec<u8>,
+    status_token: Vec<u8>,
+) -> Result<Hashes, Error> {
+    let pepper = pepper::current();
 
-    let body_reader = StreamReader::new(body_data_stream);
+    tokio::task::spawn_blocking(move || {
+        let (hash, pepper_id) = match access_secret {
+            AccessSecret::Key(key) => (Hash::hash(&key, pepper)?, pepper.map(|pepper| pepper.id)),
+            AccessSecret::VerifierHash(hash) => (hash, None),
+        };
 
-    futures::pin_mut!(body_reader);
-    tokio::io::copy(&mut body_reader, &mut body).await?;
+        Ok(Hashes {
+            hash,
+            pepper_id,
+            deletion_hash: Hash::hash(&deletion_token, None)?,
+            status_hash: Hash::hash(&status_token, None)?,
+        })
+    })
+    .await
+    .map_err(|error| Error::HashingFailure(error.to_string()))?
+}
 
-    if body.len() > CONFIGURATION.body_max_size {
-        return Err(IoError::new(
-            ErrorKind::StorageFull,
-            "Max body size exceeded",
-        ));
+/// Stores given (encrypted) `content` of a file
+///
+/// # Arguments
+///
+/// * `id` - File id
+/// * `content` - Stream of content to store
+/// * `cipher` - Cipher the file is encrypted with
+///
+/// # Returns
+///
+/// * [`Ok<file::StoredData>`] on success, containing the wrapped storage
+///   key, if content has been encrypted with one, and the stored size
+/// * [`Err<StatusCode>`] on error
+async fn store_content<S: Stream<Item = Result<Vec<u8>, Error>> + Send + 'static>(
+    id: &Uuid,
+    content: S,
+    cipher: CipherId,
+) -> Result<file::StoredData, StatusCode> {
+    match file::store_data(id, content, cipher).await {
+        Ok(stored) => Ok(stored),
+        Err(Error::ReadingDataFailed(error)) if error.kind() == ErrorKind::FileTooLarge => {
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        Err(error) => return_logged!(error, StatusCode::INTERNAL_SERVER_ERROR),
     }
+}
+
+/// Creates a reader of the body of given `request`.
+///
+/// The body is read lazily while the reader is being polled. If the body
+/// exceeds the max body size, reading fails with [`ErrorKind::FileTooLarge`].
+///
+/// # Arguments
+///
+/// * `request` - Request to read body of
+/// * `body_size` - Counter of the bytes read so far
+///
+/// # Returns
+///
+/// * Reader of the request body
+fn body_reader(
+    request: Request,
+    body_size: Arc<AtomicU64>,
+) -> impl AsyncRead + Unpin + Send + 'static {
+    StreamReader::new(body_stream(request, body_size))
+}
+
+/// Creates a stream of the body of given `request`.
+///
+/// If the body exceeds the max body size, the stream fails with
+/// [`ErrorKind::FileTooLarge`].
+///
+/// # Arguments
+///
+/// * `request` - Request to stream body of
+/// * `body_size` - Counter of the bytes streamed so far
+///
+/// # Returns
+///
+/// * Stream of chunks of the request body
+fn body_stream(
+    request: Request,
+    body_size: Arc<AtomicU64>,
+) -> impl Stream<Item = Result<Bytes, IoError>> + Unpin + Send + 'static {
+    request
+        .into_body()
+        .into_data_stream()
+        .map_err(IoError::other)
+        .and_then(move |chunk| {
+            let chunk_size = chunk.len() as u64;
+            let size = body_size.fetch_add(chunk_size, Ordering::Relaxed) + chunk_size;
 
-    Ok(body)
+            future::ready(if size > CONFIGURATION.body_max_size as u64 {
+                Err(IoError::new(
+                    ErrorKind::FileTooLarge,
+                    "Max body size exceeded",
+                ))
+            } else {
+                Ok(chunk)
+            })
+        })
 }
@@ -1,7 +1,7 @@
 use super::routes;
 use crate::configuration::CONFIGURATION;
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use laika::shotgun;
@@ -47,7 +47,14 @@ use tokio::net::TcpListener;
 pub async fn listen(connection: DatabaseConnection, shutdown: shotgun::Receiver<()>) -> Result<()> {
     let app = Router::new()
         .route("/api/files", post(routes::upload::handler))
+        .route("/api/files/{id}", delete(routes::delete::handler))
+        .route(
+            "/api/files/{id}/challenge",
+            post(routes::challenge::handler),
+        )
         .route("/api/files/{id}/download", post(routes::download::handler))
+        .route("/api/files/{id}/info", post(routes::info::handler))
+        .route("/api/files/{id}/status", get(routes::status::handler))
         .route("/api/configuration", get(routes::configuration::handler))
         .with_state(connection);
 
@@ -1,4 +1,6 @@
+use crate::configuration::CONFIGURATION;
 use crate::{database, error::Result, file};
+use chrono::Utc;
 use laika::shotgun;
 use sea_orm::DatabaseConnection;
 use std::time::Duration;
@@ -9,6 +11,11 @@ use uuid::Uuid;
 /// Currently set to 10 minutes.
 const CLEANUP_INTERVAL_SECONDS: u64 = 10 * 60; /* 10 minutes */
 
+/// The time in seconds after which incompletely stored files, that haven't
+/// been written to anymore, are considered stale and get deleted.
+/// Currently set to 1 hour.
+const STALE_STAGED_DATA_SECONDS: u64 = 60 * 60; /* 1 hour */
+
 /// Runs the cleanup process in a loop, until `shutdown` signal is received.
 ///
 /// # Arguments
@@ -34,12 +41,42 @@ pub async fn run(
 
         log::info!("Cleaning up outdating files...");
 
-        database::remove_undownloadable_files(&database_connection).await?;
+        database::remove_expired_files(&database_connection).await?;
+        remove_expired_ips(&database_connection).await?;
         delete_outdated_files(&database_connection).await?;
+        file::purge_stale_staged_data(Duration::from_secs(STALE_STAGED_DATA_SECONDS)).await?;
+
+        let usage = database::get_storage_usage(&database_connection).await?;
+        log::info!("Storing {} files of {} bytes", usage.files, usage.size);
     }
 }
 
-/// Deletes outdated files from the file system.
+/// Removes IPs that are older than their configured retention from the
+/// database.
+///
+/// # Arguments
+///
+/// * `database_connection` - A connection to the database.
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on successful cleanup
+/// * [`Err<Error>`] on error
+async fn remove_expired_ips(database_connection: &DatabaseConnection) -> Result<()> {
+    let now = Utc::now();
+
+    if let Some(retention) = CONFIGURATION.access_log_retention {
+        database::remove_access_logs_before(database_connection, now - retention).await?;
+    }
+
+    if let Some(retention) = CONFIGURATION.uploader_ip_retention {
+        database::remove_uploader_ips_before(database_connection, now - retention).await?;
+    }
+
+    Ok(())
+}
+
+/// Deletes outdated files from the storage backend.
 ///
 /// # Arguments
 ///
@@ -52,7 +89,7 @@ pub async fn run(
 async fn delete_outdated_files(database_connection: &DatabaseConnection) -> Result<()> {
     let downloadable_file_ids = database::get_downloadable_file_ids(database_connection).await?;
 
-    let stored_file_ids = file::get_stored_file_ids()?;
+    let stored_file_ids = file::get_stored_file_ids().await?;
 
     let file_ids_to_delete = stored_file_ids
         .iter()
@@ -64,7 +101,7 @@ async fn delete_outdated_files(database_connection: &DatabaseConnection) -> Resu
         .collect::<Vec<&Uuid>>();
 
     for file_id in file_ids_to_delete {
-        file::delete(file_id)?;
+        file::delete(file_id).await?;
         log::info!("Deleted outdated file: {file_id}");
     }
 
@@ -1,10 +1,26 @@
-use chrono::Days;
+use crate::encryption::master_key::{self, MasterKey};
+use crate::encryption::CipherId;
+use crate::error::Error;
+use crate::hash::pepper::{self, Pepper};
+use crate::ip::{self, Anonymization};
+use argon2::Params;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use chrono::TimeDelta;
 use config::{Environment, File, FileFormat};
+use log::LevelFilter;
 use serde::Deserialize;
-use std::{path::PathBuf, process::exit, sync::LazyLock};
+use std::{env, fs, io, path::PathBuf, process::exit, sync::LazyLock, time::Duration};
 
 pub const CONFIG_FILE_NAME: &str = "config.json";
 pub const CONFIG_ENV_PREFIX: &str = "TREASURE_CHEST";
+/// Name of env var containing peppers, see [`pepper::parse`]
+pub const PEPPERS_ENV_VAR: &str = "TREASURE_CHEST_PEPPERS";
+/// Name of env var containing master keys, see [`master_key::parse`]
+pub const MASTER_KEYS_ENV_VAR: &str = "TREASURE_CHEST_MASTER_KEYS";
+/// Name of env var containing the key that IPs are hashed with, see
+/// [`Anonymization::Hmac`]
+pub const IP_HMAC_KEY_ENV_VAR: &str = "TREASURE_CHEST_IP_HMAC_KEY";
 
 pub static CONFIGURATION: LazyLock<Configuration> = LazyLock::new(build);
 
@@ -14,42 +30,231 @@ pub static CONFIGURATION: LazyLock<Configuration> = LazyLock::new(build);
 struct RawConfiguration {
     #[serde(rename = "ConnectionString")]
     pub connection_string: String,
+    #[serde(
+        rename = "DatabaseMaxConnections",
+        default = "default_database_max_connections"
+    )]
+    pub database_max_connections: u32,
+    #[serde(
+        rename = "DatabaseMinConnections",
+        default = "default_database_min_connections"
+    )]
+    pub database_min_connections: u32,
+    #[serde(
+        rename = "DatabaseConnectTimeoutSeconds",
+        default = "default_database_timeout_seconds"
+    )]
+    pub database_connect_timeout_seconds: u64,
+    #[serde(
+        rename = "DatabaseAcquireTimeoutSeconds",
+        default = "default_database_timeout_seconds"
+    )]
+    pub database_acquire_timeout_seconds: u64,
+    #[serde(
+        rename = "DatabaseIdleTimeoutSeconds",
+        default = "default_database_idle_timeout_seconds"
+    )]
+    pub database_idle_timeout_seconds: u64,
+    #[serde(
+        rename = "DatabaseMaxLifetimeSeconds",
+        default = "default_database_max_lifetime_seconds"
+    )]
+    pub database_max_lifetime_seconds: u64,
+    #[serde(rename = "DatabaseLogLevel", default = "default_database_log_level")]
+    pub database_log_level: String,
+    #[serde(
+        rename = "DatabaseConnectRetries",
+        default = "default_database_connect_retries"
+    )]
+    pub database_connect_retries: u32,
+    #[serde(
+        rename = "DatabaseRetryDelaySeconds",
+        default = "default_database_retry_delay_seconds"
+    )]
+    pub database_retry_delay_seconds: u64,
     #[serde(rename = "BindTo")]
     pub listening_address: String,
+    #[serde(rename = "Storage", default)]
+    pub storage: StorageType,
     #[serde(rename = "FilePath")]
-    pub file_path: PathBuf,
+    pub file_path: Option<PathBuf>,
+    #[serde(rename = "S3")]
+    pub s3: Option<S3Configuration>,
     #[serde(rename = "DaysFileAvailable")]
     pub days_file_available: u64,
+    #[serde(rename = "MaxDaysFileAvailable")]
+    pub max_days_file_available: Option<u64>,
+    #[serde(
+        rename = "MaxDownloadsPerFile",
+        default = "default_max_downloads_per_file"
+    )]
+    pub max_downloads_per_file: u32,
     #[serde(rename = "UserUploadsPerDay")]
     pub user_uploads_per_day: u32,
     #[serde(rename = "MaxDownloadTries")]
     pub max_download_tries: u32,
     #[serde(rename = "IpHeaderName")]
     pub ip_header_name: String,
+    #[serde(rename = "IpHeaderPosition", default)]
+    pub ip_header_position: i32,
+    #[serde(rename = "IpAnonymization", default)]
+    pub ip_anonymization: IpAnonymizationType,
+    #[serde(rename = "IpHmacKeyFile")]
+    pub ip_hmac_key_file: Option<PathBuf>,
+    #[serde(rename = "AccessLogRetentionDays")]
+    pub access_log_retention_days: Option<u64>,
+    #[serde(rename = "UploaderIpRetentionDays")]
+    pub uploader_ip_retention_days: Option<u64>,
     #[serde(rename = "BodyMaxSize")]
     pub body_max_size: usize,
+    #[serde(rename = "TwoPhaseDownload", default)]
+    pub two_phase_download: bool,
+    #[serde(
+        rename = "DownloadReservationSeconds",
+        default = "default_download_reservation_seconds"
+    )]
+    pub download_reservation_seconds: u32,
+    #[serde(rename = "Cipher", default)]
+    pub cipher: CipherId,
+    #[serde(rename = "Argon2MemoryCost", default = "default_argon2_memory_cost")]
+    pub argon2_memory_cost: u32,
+    #[serde(rename = "Argon2TimeCost", default = "default_argon2_time_cost")]
+    pub argon2_time_cost: u32,
+    #[serde(rename = "Argon2Parallelism", default = "default_argon2_parallelism")]
+    pub argon2_parallelism: u32,
+    #[serde(rename = "PepperFile")]
+    pub pepper_file: Option<PathBuf>,
+    #[serde(rename = "MasterKeyFile")]
+    pub master_key_file: Option<PathBuf>,
+}
+
+/// Type of storage backend, as given in configuration
+#[derive(Deserialize, Default)]
+enum StorageType {
+    /// Files are stored in a local directory
+    #[default]
+    Local,
+    /// Files are stored in an S3-compatible object store
+    S3,
+}
+
+/// Kind of IP anonymization, as given in configuration
+#[derive(Deserialize, Default)]
+enum IpAnonymizationType {
+    /// IPs are stored as they are
+    #[default]
+    None,
+    /// IPs are replaced by their HMAC with a configured key
+    Hmac,
+    /// IPs are truncated to their network prefix
+    Truncate,
+}
+
+/// Configuration of an S3-compatible object store
+#[derive(Deserialize)]
+pub struct S3Configuration {
+    /// Endpoint of object store (e.g. "_http://localhost:9000_"), AWS if unset
+    #[serde(rename = "Endpoint")]
+    pub endpoint: Option<String>,
+    /// Region of bucket
+    #[serde(rename = "Region", default = "default_s3_region")]
+    pub region: String,
+    /// Name of bucket
+    #[serde(rename = "Bucket")]
+    pub bucket: String,
+    /// Prefix of object keys
+    #[serde(rename = "Prefix", default)]
+    pub prefix: String,
+    /// Access key id
+    #[serde(rename = "AccessKeyId")]
+    pub access_key_id: String,
+    /// Secret access key
+    #[serde(rename = "SecretAccessKey")]
+    pub secret_access_key: String,
+    /// Whether the endpoint may be accessed without TLS (e.g. a local MinIO)
+    #[serde(rename = "AllowHttp", default)]
+    pub allow_http: bool,
+}
+
+/// Settings of the database connection pool
+pub struct DatabasePoolConfiguration {
+    /// Max number of open connections
+    pub max_connections: u32,
+    /// Min number of connections kept open
+    pub min_connections: u32,
+    /// Time to wait for a new connection to be established
+    pub connect_timeout: Duration,
+    /// Time to wait for a connection from the pool
+    pub acquire_timeout: Duration,
+    /// Time after which idle connections are closed
+    pub idle_timeout: Duration,
+    /// Time after which connections are closed and replaced
+    pub max_lifetime: Duration,
+    /// Level that executed SQL statements are logged with
+    pub log_level: LevelFilter,
+    /// Number of retries if the database can't be connected to on startup
+    pub connect_retries: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub retry_delay: Duration,
+}
+
+/// Storage of encrypted files
+pub enum StorageConfiguration {
+    /// Files are stored in given local directory
+    Local(PathBuf),
+    /// Files are stored in given S3-compatible object store
+    S3(S3Configuration),
 }
 
 /// Configuration of program
 pub struct Configuration {
     /// Database connection string
     pub connection_string: String,
+    /// Settings of the database connection pool
+    pub database_pool: DatabasePoolConfiguration,
     /// Address to listen to (e.g. "_localhost:8080_")
     pub listening_address: String,
-    /// Path of encrypted files
-    pub file_path: PathBuf,
-    /// Lifetime of uploaded files until deletion
-    pub file_lifetime: Days,
+    /// Storage of encrypted files
+    pub storage: StorageConfiguration,
+    /// Default lifetime of uploaded files until deletion
+    pub file_lifetime: TimeDelta,
     /// Raw value of `file_lifetime`
     pub days_file_available: u64,
+    /// Max lifetime of uploaded files that uploaders may choose
+    pub max_file_lifetime: TimeDelta,
+    /// Raw value of `max_file_lifetime`
+    pub max_days_file_available: u64,
+    /// Max number of downloads of a single file that uploaders may choose
+    pub max_downloads_per_file: u32,
     /// Number of max uploads by a single IP (rate limiting)
     pub ip_uploads_per_day: u32,
     /// Number of max tries to access a file (in case of wrong keys etc)
     pub max_download_tries: u32,
     /// Name of IP header, set by proxy server
     pub ip_header_name: String,
+    /// Position of the client IP in the IP header, see [`ip::get_client_ip`]
+    pub ip_header_position: i32,
+    /// How client IPs are anonymized before they are stored
+    pub ip_anonymization: Anonymization,
+    /// Time after which access log entries and revocations are deleted
+    pub access_log_retention: Option<TimeDelta>,
+    /// Time after which IPs of uploaders are removed from their files
+    pub uploader_ip_retention: Option<TimeDelta>,
     /// Max size of request body (in bytes)
     pub body_max_size: usize,
+    /// Whether files are only marked as downloaded after their transfer has
+    /// completed, instead of right before the transfer
+    pub two_phase_download: bool,
+    /// Time a file stays reserved for a two-phase download without progress
+    pub download_reservation_time: TimeDelta,
+    /// Cipher that new uploads are encrypted with
+    pub cipher: CipherId,
+    /// Parameters that keys and tokens are hashed with
+    pub argon2_params: Params,
+    /// Peppers that are mixed into hashes of file encryption keys
+    pub peppers: Vec<Pepper>,
+    /// Master keys that storage keys of files are wrapped with
+    pub master_keys: Vec<MasterKey>,
 }
 
 /// Builds [`Configuration`] by configuration file and env vars
@@ -73,15 +278,249 @@ pub fn build() -> Configuration {
         exit(1);
     };
 
+    let storage = match (raw.storage, raw.file_path, raw.s3) {
+        (StorageType::Local, Some(file_path), _) => StorageConfiguration::Local(file_path),
+        (StorageType::S3, _, Some(s3)) => StorageConfiguration::S3(s3),
+        _ => {
+            log::error!("Configuration of storage is incomplete. Bye.");
+            exit(1);
+        }
+    };
+
+    let Ok(database_log_level) = raw.database_log_level.parse::<LevelFilter>() else {
+        log::error!("Configuration of database log level is invalid. Bye.");
+        exit(1);
+    };
+
+    if raw.database_max_connections == 0
+        || raw.database_min_connections > raw.database_max_connections
+    {
+        log::error!("Configuration of database pool size is invalid. Bye.");
+        exit(1);
+    }
+
+    let database_pool = DatabasePoolConfiguration {
+        max_connections: raw.database_max_connections,
+        min_connections: raw.database_min_connections,
+        connect_timeout: Duration::from_secs(raw.database_connect_timeout_seconds),
+        acquire_timeout: Duration::from_secs(raw.database_acquire_timeout_seconds),
+        idle_timeout: Duration::from_secs(raw.database_idle_timeout_seconds),
+        max_lifetime: Duration::from_secs(raw.database_max_lifetime_seconds),
+        log_level: database_log_level,
+        connect_retries: raw.database_connect_retries,
+        retry_delay: Duration::from_secs(raw.database_retry_delay_seconds),
+    };
+
+    let max_days_file_available = raw
+        .max_days_file_available
+        .unwrap_or(raw.days_file_available);
+
+    let (Some(file_lifetime), Some(max_file_lifetime)) = (
+        days_to_time_delta(raw.days_file_available),
+        days_to_time_delta(max_days_file_available),
+    ) else {
+        log::error!("Configuration of file lifetime is invalid. Bye.");
+        exit(1);
+    };
+
+    if file_lifetime > max_file_lifetime || raw.max_downloads_per_file == 0 {
+        log::error!("Configuration of file limits is invalid. Bye.");
+        exit(1);
+    }
+
+    /* Reservations must outlast the time between two reads of a transfer */
+    if raw.download_reservation_seconds == 0 {
+        log::error!("Configuration of download reservation must be at least 1 second. Bye.");
+        exit(1);
+    }
+
+    let (Some(access_log_retention), Some(uploader_ip_retention)) = (
+        raw.access_log_retention_days
+            .map_or(Some(None), |days| days_to_time_delta(days).map(Some)),
+        raw.uploader_ip_retention_days
+            .map_or(Some(None), |days| days_to_time_delta(days).map(Some)),
+    ) else {
+        log::error!("Configuration of retention is invalid. Bye.");
+        exit(1);
+    };
+
+    /* The upload limit needs uploader IPs of the last day */
+    if raw.uploader_ip_retention_days == Some(0) {
+        log::error!("Configuration of uploader IP retention must be at least 1 day. Bye.");
+        exit(1);
+    }
+
+    let ip_anonymization = match raw.ip_anonymization {
+        IpAnonymizationType::None => Anonymization::None,
+        IpAnonymizationType::Truncate => Anonymization::Truncate,
+        IpAnonymizationType::Hmac => {
+            let key = read_secrets(IP_HMAC_KEY_ENV_VAR, raw.ip_hmac_key_file)
+                .ok()
+                .and_then(|text| BASE64_STANDARD.decode(text.trim()).ok())
+                .filter(|key| key.len() >= ip::MIN_HMAC_KEY_LENGTH);
+
+            let Some(key) = key else {
+                log::error!(
+                    "Configuration of IP HMAC key is invalid, it must be at least {} bytes long. Bye.",
+                    ip::MIN_HMAC_KEY_LENGTH
+                );
+                exit(1);
+            };
+
+            Anonymization::Hmac(key)
+        }
+    };
+
+    let Ok(argon2_params) = Params::new(
+        raw.argon2_memory_cost,
+        raw.argon2_time_cost,
+        raw.argon2_parallelism,
+        None,
+    ) else {
+        log::error!("Configuration of Argon2 parameters is invalid. Bye.");
+        exit(1);
+    };
+
+    let peppers = match read_secrets(PEPPERS_ENV_VAR, raw.pepper_file)
+        .map_err(|error| Error::InvalidPepperConfiguration(error.to_string()))
+        .and_then(|text| pepper::parse(&text))
+    {
+        Ok(peppers) => peppers,
+        Err(error) => {
+            log::error!("Could not load peppers: {error:?}. Bye.");
+            exit(1);
+        }
+    };
+
+    let master_keys = match read_secrets(MASTER_KEYS_ENV_VAR, raw.master_key_file)
+        .map_err(|error| Error::InvalidMasterKeyConfiguration(error.to_string()))
+        .and_then(|text| master_key::parse(&text))
+    {
+        Ok(master_keys) => master_keys,
+        Err(error) => {
+            log::error!("Could not load master keys: {error:?}. Bye.");
+            exit(1);
+        }
+    };
+
     Configuration {
         connection_string: raw.connection_string,
+        database_pool,
         listening_address: raw.listening_address,
-        file_path: raw.file_path,
-        file_lifetime: Days::new(raw.days_file_available),
+        storage,
+        file_lifetime,
         days_file_available: raw.days_file_available,
+        max_file_lifetime,
+        max_days_file_available,
+        max_downloads_per_file: raw.max_downloads_per_file,
         max_download_tries: raw.max_download_tries,
         ip_uploads_per_day: raw.user_uploads_per_day,
         ip_header_name: raw.ip_header_name,
+        ip_header_position: raw.ip_header_position,
+        ip_anonymization,
+        access_log_retention,
+        uploader_ip_retention,
         body_max_size: raw.body_max_size,
+        two_phase_download: raw.two_phase_download,
+        download_reservation_time: TimeDelta::seconds(raw.download_reservation_seconds.into()),
+        cipher: raw.cipher,
+        argon2_params,
+        peppers,
+        master_keys,
     }
 }
+
+/// Reads secrets (e.g. peppers) from env var `env_var` or, if it isn't set,
+/// from given `file`
+///
+/// # Returns
+///
+/// * [`Ok<String>`] on success, empty if neither is given
+/// * [`Err<io::Error>`] if `file` can't be read
+fn read_secrets(env_var: &str, file: Option<PathBuf>) -> io::Result<String> {
+    if let Ok(secrets) = env::var(env_var) {
+        return Ok(secrets);
+    }
+
+    file.map_or(Ok(String::new()), fs::read_to_string)
+}
+
+/// Converts given number of `days` into a [`TimeDelta`]
+///
+/// # Returns
+///
+/// * [`Some<TimeDelta>`] on success
+/// * [`None`] if `days` is out of range
+fn days_to_time_delta(days: u64) -> Option<TimeDelta> {
+    i64::try_from(days).ok().and_then(TimeDelta::try_days)
+}
+
+/// Default max number of open database connections
+fn default_database_max_connections() -> u32 {
+    10
+}
+
+/// Default min number of open database connections
+fn default_database_min_connections() -> u32 {
+    1
+}
+
+/// Default time in seconds to wait for a database connection
+fn default_database_timeout_seconds() -> u64 {
+    8
+}
+
+/// Default time in seconds after which idle database connections are closed
+fn default_database_idle_timeout_seconds() -> u64 {
+    600
+}
+
+/// Default time in seconds after which database connections are replaced
+fn default_database_max_lifetime_seconds() -> u64 {
+    1800
+}
+
+/// Default level that executed SQL statements are logged with
+fn default_database_log_level() -> String {
+    "Debug".into()
+}
+
+/// Default number of retries to connect to the database on startup
+fn default_database_connect_retries() -> u32 {
+    5
+}
+
+/// Default delay in seconds before retrying to connect to the database
+fn default_database_retry_delay_seconds() -> u64 {
+    1
+}
+
+/// Default max number of downloads of a single file
+fn default_max_downloads_per_file() -> u32 {
+    1
+}
+
+/// Default region of S3-compatible object stores
+fn default_s3_region() -> String {
+    "us-east-1".into()
+}
+
+/// Default time in seconds a file stays reserved for a two-phase download
+fn default_download_reservation_seconds() -> u32 {
+    60
+}
+
+/// Default memory cost (in KiB) of Argon2 hashing
+fn default_argon2_memory_cost() -> u32 {
+    Params::DEFAULT_M_COST
+}
+
+/// Default number of iterations of Argon2 hashing
+fn default_argon2_time_cost() -> u32 {
+    Params::DEFAULT_T_COST
+}
+
+/// Default degree of parallelism of Argon2 hashing
+fn default_argon2_parallelism() -> u32 {
+    Params::DEFAULT_P_COST
+}
@@ -1,10 +1,13 @@
 use super::error::{Error, Result};
 use crate::configuration::CONFIGURATION;
-use chrono::{Days, Utc};
-use migration::ExprTrait;
-use sea_orm::sea_query::Query;
-use sea_orm::{ColumnTrait, Condition, FromQueryResult};
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, Set};
+use crate::encryption::master_key::WrappedStorageKey;
+use crate::encryption::CipherId;
+use crate::recipient::RecipientKey;
+use chrono::{DateTime, Days, NaiveDateTime, TimeDelta, Utc};
+use entity::sea_orm_active_enums::AccessAction;
+use sea_orm::sea_query::{Alias, Expr, SimpleExpr};
+use sea_orm::{ColumnTrait, Condition, ConnectionTrait, DbBackend, FromQueryResult};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, Set, TransactionTrait};
 use uuid::Uuid;
 
 /// Wrapper for `COUNT(*)` queries
@@ -13,9 +16,30 @@ struct CountResult {
     count: i64,
 }
 
+/// Summary of all accesses of a file
+#[derive(Debug, Default, PartialEq)]
+pub struct AccessSummary {
+    /// Number of successful downloads
+    pub successful_downloads: u32,
+    /// Time of the last successful download
+    pub last_downloaded_at: Option<DateTime<Utc>>,
+    /// Number of failed download attempts
+    pub failed_attempts: u32,
+}
+
+/// Storage used by files, see [`get_storage_usage`]
+#[derive(Debug, PartialEq)]
+pub struct StorageUsage {
+    /// Number of stored files
+    pub files: u64,
+    /// Total size of stored, encrypted content in bytes
+    pub size: u64,
+}
+
 /// Gets file from database for id that can currently be downloaded
 ///
-/// Checks if file has already been downloaded and if it's still in time range.
+/// Checks if file has already been downloaded as often as allowed, if it has
+/// been accessed unsuccessfully too often and if it's still in time range.
 ///
 /// # Arguments
 ///
@@ -32,33 +56,10 @@ pub async fn get_downloadable_file(
     id: &Uuid,
 ) -> Result<Option<entity::file::Model>> {
     entity::File::find()
-        .filter(entity::file::Column::Id.eq(*id))
-        .filter(entity::file::Column::DownloadUntil.gte(Utc::now()))
-        .filter(
-            entity::file::Column::Id.not_in_subquery(
-                Query::select()
-                    .column(entity::access_log::Column::FileId)
-                    .from(entity::access_log::Entity)
-                    .cond_where(Condition::all().add(entity::access_log::Column::Successful.eq(1)))
-                    .to_owned(),
-            ),
-        )
-        .filter(
-            entity::file::Column::Id.not_in_subquery(
-                Query::select()
-                    .column(entity::access_log::Column::FileId)
-                    .from(entity::access_log::Entity)
-                    .group_by_col(entity::access_log::Column::FileId)
-                    .cond_having(
-                        Condition::all().add(
-                            entity::access_log::Column::FileId
-                                .count()
-                                .gte(CONFIGURATION.max_download_tries),
-                        ),
-                    )
-                    .to_owned(),
-            ),
-        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*id)))
+        .filter(entity::file::Column::DownloadUntil.gte(Utc::now().naive_utc()))
+        .filter(has_downloads_left())
+        .filter(has_attempts_left())
         .one(database_connection)
         .await
         .map_err(Error::DatabaseOperationFailed)
@@ -66,7 +67,9 @@ pub async fn get_downloadable_file(
 
 /// Gets all file ids from database that can currently be downloaded
 ///
-/// Checks if file has already been downloaded and if it's still in time range.
+/// Checks if file has already been downloaded as often as allowed, if it has
+/// been accessed unsuccessfully too often and if it's still in time range.
+/// Files that are currently being downloaded are always included.
 ///
 /// # Arguments
 ///
@@ -80,32 +83,13 @@ pub async fn get_downloadable_file_ids(
     database_connection: &DatabaseConnection,
 ) -> Result<Vec<Uuid>> {
     entity::File::find()
-        .filter(entity::file::Column::DownloadUntil.gte(Utc::now()))
         .filter(
-            entity::file::Column::Id.not_in_subquery(
-                Query::select()
-                    .column(entity::access_log::Column::FileId)
-                    .from(entity::access_log::Entity)
-                    .cond_where(Condition::all().add(entity::access_log::Column::Successful.eq(1)))
-                    .to_owned(),
-            ),
-        )
-        .filter(
-            entity::file::Column::Id.not_in_subquery(
-                Query::select()
-                    .column(entity::access_log::Column::FileId)
-                    .from(entity::access_log::Entity)
-                    .group_by_col(entity::access_log::Column::FileId)
-                    .cond_having(
-                        Condition::all().add(
-                            entity::access_log::Column::FileId
-                                .count()
-                                .gte(CONFIGURATION.max_download_tries),
-                        ),
-                    )
-                    .to_owned(),
-            ),
+            Condition::any()
+                .add(entity::file::Column::DownloadUntil.gte(Utc::now().naive_utc()))
+                .add(is_not_reserved().not()),
         )
+        .filter(has_downloads_left())
+        .filter(has_attempts_left())
         .select_only()
         .column(entity::file::Column::Id)
         .into_tuple()
@@ -114,11 +98,15 @@ pub async fn get_downloadable_file_ids(
         .map_err(Error::DatabaseOperationFailed)
 }
 
-/// Removes undownloadable files from the database.
+/// Removes expired files from the database.
 ///
-/// This function deletes files that are either past their download expiration
-/// date, have been successfully downloaded, or have exceeded the maximum
-/// number of allowed download attempts.
+/// This function deletes files that are past their download expiration date.
+/// Files that have been downloaded or have exceeded the maximum number of
+/// allowed download attempts are kept until then, so that uploaders can still
+/// query their status. Only their content is deleted right away, see
+/// [`get_downloadable_file_ids`]. Their uploader IP and access log are kept
+/// unless configured retention removes them earlier. Files are also kept
+/// while they are being downloaded.
 ///
 /// # Arguments
 ///
@@ -129,41 +117,70 @@ pub async fn get_downloadable_file_ids(
 ///
 /// * [`Ok<()>`] on success
 /// * [`Err<Error>`] on error
-pub async fn remove_undownloadable_files(database_connection: &DatabaseConnection) -> Result<()> {
+pub async fn remove_expired_files(database_connection: &DatabaseConnection) -> Result<()> {
     entity::File::delete_many()
-        .filter(
-            Condition::any()
-                .add(entity::file::Column::DownloadUntil.lt(Utc::now()))
-                .add(
-                    entity::file::Column::Id.in_subquery(
-                        Query::select()
-                            .column(entity::access_log::Column::FileId)
-                            .from(entity::access_log::Entity)
-                            .cond_where(
-                                Condition::all().add(entity::access_log::Column::Successful.eq(1)),
-                            )
-                            .to_owned(),
-                    ),
-                )
-                .add(
-                    entity::file::Column::Id
-                        .in_subquery(
-                            Query::select()
-                                .column(entity::access_log::Column::FileId)
-                                .from(entity::access_log::Entity)
-                                .group_by_col(entity::access_log::Column::FileId)
-                                .cond_having(
-                                    Condition::all().add(
-                                        entity::access_log::Column::FileId
-                                            .count()
-                                            .gte(CONFIGURATION.max_download_tries),
-                                    ),
-                                )
-                                .to_owned(),
-                        )
-                        .to_owned(),
-                ),
-        )
+        .filter(entity::file::Column::DownloadUntil.lt(Utc::now().naive_utc()))
+        .filter(is_not_reserved())
+        .exec(database_connection)
+        .await
+        .map(|_| ())
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Removes access log entries and revocations that have been recorded before
+/// given `before`
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `before` - Entries recorded before are removed
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn remove_access_logs_before(
+    database_connection: &DatabaseConnection,
+    before: DateTime<Utc>,
+) -> Result<()> {
+    entity::AccessLog::delete_many()
+        .filter(entity::access_log::Column::DateTime.lt(before.naive_utc()))
+        .exec(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    entity::Revocation::delete_many()
+        .filter(entity::revocation::Column::DateTime.lt(before.naive_utc()))
+        .exec(database_connection)
+        .await
+        .map(|_| ())
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Removes the uploader IPs of files that have been uploaded before given
+/// `before`
+///
+/// IPs are replaced by an empty string, so files don't count towards the
+/// upload limit of their uploader anymore. `before` must thus be at least a
+/// day ago.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `before` - IPs of files uploaded before are removed
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn remove_uploader_ips_before(
+    database_connection: &DatabaseConnection,
+    before: DateTime<Utc>,
+) -> Result<()> {
+    entity::File::update_many()
+        .col_expr(entity::file::Column::UploaderIp, Expr::value(""))
+        .filter(entity::file::Column::UploadedAt.lt(before.naive_utc()))
+        .filter(entity::file::Column::UploaderIp.ne(""))
         .exec(database_connection)
         .await
         .map(|_| ())
@@ -205,79 +222,1193 @@ pub async fn is_upload_limit_reached(
     Ok(count >= CONFIGURATION.ip_uploads_per_day.into())
 }
 
-/// Store new file entry to database
+/// Returns the number of stored files and their total stored size
+///
+/// Files uploaded before sizes were recorded don't count towards the size.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+///
+/// # Returns
+///
+/// * [`Ok<StorageUsage>`] on success
+/// * [`Err<Error>`] on error
+pub async fn get_storage_usage(database_connection: &DatabaseConnection) -> Result<StorageUsage> {
+    /* Sums of integers are decimals on MySQL and PostgreSQL */
+    let size_type = match database_connection.get_database_backend() {
+        DbBackend::MySql => "SIGNED",
+        _ => "BIGINT",
+    };
+
+    let (files, size): (i64, Option<i64>) = entity::File::find()
+        .select_only()
+        .column_as(entity::file::Column::Id.count(), "files")
+        .column_as(
+            entity::file::Column::CiphertextSize
+                .sum()
+                .cast_as(Alias::new(size_type)),
+            "size",
+        )
+        .into_tuple()
+        .one(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?
+        .unwrap_or_default();
+
+    Ok(StorageUsage {
+        files: files.try_into().unwrap_or_default(),
+        size: size.unwrap_or_default().try_into().unwrap_or_default(),
+    })
+}
+
+/// New file entry to store to database, see [`store_file`]
+pub struct NewFile {
+    /// Id of new file
+    pub id: Uuid,
+    /// Encryption key hash
+    pub hash: String,
+    /// Id of the pepper mixed into the encryption key hash, if any
+    pub pepper_id: Option<i16>,
+    /// Deletion token hash
+    pub deletion_hash: String,
+    /// Status token hash
+    pub status_hash: String,
+    /// Ip of client uploading this file
+    pub uploader_ip: String,
+    /// File metadata in encrypted form
+    pub encrypted_metadata: Vec<u8>,
+    /// Time the file can be downloaded for
+    pub lifetime: TimeDelta,
+    /// Number of times the file can be downloaded
+    pub max_downloads: u32,
+    /// Cipher the file has been encrypted with
+    pub cipher: CipherId,
+    /// Salt of the key derived from the passphrase, if file is protected by
+    /// a passphrase
+    pub key_salt: Option<Vec<u8>>,
+    /// Encryption key wrapped with a key derived from the passphrase, if file
+    /// is protected by a passphrase
+    pub wrapped_key: Option<Vec<u8>>,
+    /// Encoded parameters the key has been derived from the passphrase with,
+    /// if file is protected by a passphrase
+    pub key_params: Option<String>,
+    /// Whether the file has been encrypted by the client
+    pub client_encrypted: bool,
+    /// Encryption keys wrapped for the recipients of the file, if any
+    pub recipients: Vec<RecipientKey>,
+    /// Wrapped storage key, if stored data has been encrypted with one
+    pub storage_key: Option<WrappedStorageKey>,
+    /// Size of the uploaded content in bytes, as served on download
+    pub plaintext_size: u64,
+    /// Size of the stored, encrypted content in bytes
+    pub ciphertext_size: u64,
+}
+
+/// Store new file entry to database, along with its recipients
 ///
 /// # Arguments
 ///
 /// * `database_connection` - [`DatabaseConnection`] to use
-/// * `id` - Id of new file
-/// * `hash` - Encryption key hash
-/// * `uploader_ip` - Ip of client uploading this file
-/// * `encrypted_metadata` - File metadata in encrypted form
+/// * `file` - New file entry
 ///
 /// # Returns
 ///
 /// * [`Ok<()>`] on success
 /// * [`Err<Error>`] on error
-pub async fn store_file(
-    database_connection: &DatabaseConnection,
-    id: &Uuid,
-    hash: String,
-    uploader_ip: String,
-    encrypted_metadata: Vec<u8>,
-) -> Result<()> {
+pub async fn store_file(database_connection: &DatabaseConnection, file: NewFile) -> Result<()> {
     let now = Utc::now();
 
     let download_until = now
-        .checked_add_days(CONFIGURATION.file_lifetime)
+        .checked_add_signed(file.lifetime)
         .ok_or(Error::DateCalculationFailed)?;
 
+    let recipients = file
+        .recipients
+        .into_iter()
+        .map(|recipient| entity::recipient::ActiveModel {
+            id: Set(Uuid::new_v4().into()),
+            file_id: Set(file.id.into()),
+            public_key: Set(recipient.public_key),
+            ephemeral_public_key: Set(recipient.ephemeral_public_key),
+            wrapped_key: Set(recipient.wrapped_key),
+            challenge_secret: Set(None),
+            challenge_until: Set(None),
+        })
+        .collect::<Vec<_>>();
+
+    let (master_key_id, wrapped_storage_key) = file
+        .storage_key
+        .map(|storage_key| (storage_key.master_key_id, storage_key.wrapped_key))
+        .unzip();
+
     let file = entity::file::ActiveModel {
-        id: Set((*id).into()),
-        hash: Set(hash),
-        uploader_ip: Set(uploader_ip),
+        id: Set(file.id.into()),
+        hash: Set(file.hash),
+        uploader_ip: Set(file.uploader_ip),
         uploaded_at: Set(now.naive_utc()),
         download_until: Set(download_until.naive_utc()),
-        encrypted_metadata: Set(encrypted_metadata),
+        encrypted_metadata: Set(file.encrypted_metadata),
+        successful_downloads: Set(0),
+        max_downloads: Set(file.max_downloads.try_into().unwrap_or(i32::MAX)),
+        reservation_id: Set(None),
+        reserved_until: Set(None),
+        deletion_hash: Set(Some(file.deletion_hash)),
+        status_hash: Set(Some(file.status_hash)),
+        cipher: Set(file.cipher as i16),
+        key_salt: Set(file.key_salt),
+        wrapped_key: Set(file.wrapped_key),
+        key_params: Set(file.key_params),
+        client_encrypted: Set(file.client_encrypted),
+        pepper_id: Set(file.pepper_id),
+        master_key_id: Set(master_key_id),
+        wrapped_storage_key: Set(wrapped_storage_key),
+        failed_attempts: Set(0),
+        plaintext_size: Set(Some(file.plaintext_size.try_into().unwrap_or(i64::MAX))),
+        ciphertext_size: Set(Some(file.ciphertext_size.try_into().unwrap_or(i64::MAX))),
     };
 
+    let transaction = database_connection
+        .begin()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
     entity::File::insert(file)
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    if !recipients.is_empty() {
+        entity::Recipient::insert_many(recipients)
+            .exec(&transaction)
+            .await
+            .map_err(Error::DatabaseOperationFailed)?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Gets file from database for id, regardless of whether it can currently be
+/// downloaded
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `id` - Id of the file entry
+///
+/// # Returns
+///
+/// * [`Ok<Some<Model>>`] containing file model
+/// * [`Ok<None>`] on file not existing
+/// * [`Err<Error>`] on error
+pub async fn get_file(
+    database_connection: &DatabaseConnection,
+    id: &Uuid,
+) -> Result<Option<entity::file::Model>> {
+    entity::File::find_by_id(Vec::<u8>::from(*id))
+        .one(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Summarizes all accesses of given `file`
+///
+/// Numbers of downloads and failed attempts are taken from the file itself,
+/// so they stay the same after access log entries have been removed. Failed
+/// attempts of all kinds of access are counted, as they all count towards the
+/// max download tries. Only the time of the last download is taken from the
+/// retained access log entries.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file` - File to summarize accesses of
+///
+/// # Returns
+///
+/// * [`Ok<AccessSummary>`] on success
+/// * [`Err<Error>`] on error
+pub async fn get_access_summary(
+    database_connection: &DatabaseConnection,
+    file: &entity::file::Model,
+) -> Result<AccessSummary> {
+    let last_downloaded_at: Option<Option<NaiveDateTime>> = entity::AccessLog::find()
+        .select_only()
+        .column_as(entity::access_log::Column::DateTime.max(), "last")
+        .filter(entity::access_log::Column::FileId.eq(file.id.clone()))
+        .filter(entity::access_log::Column::Successful.eq(true))
+        .filter(entity::access_log::Column::Action.eq(AccessAction::Download))
+        .into_tuple()
+        .one(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    Ok(AccessSummary {
+        successful_downloads: file.successful_downloads.try_into().unwrap_or_default(),
+        last_downloaded_at: last_downloaded_at.flatten().map(|last| last.and_utc()),
+        failed_attempts: file.failed_attempts.try_into().unwrap_or_default(),
+    })
+}
+
+/// Replaces the hash of the encryption key of a file
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the file
+/// * `hash` - New hash
+/// * `pepper_id` - Id of the pepper mixed into the new hash, if any
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn update_hash(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    hash: String,
+    pepper_id: Option<i16>,
+) -> Result<()> {
+    entity::File::update_many()
+        .col_expr(entity::file::Column::Hash, Expr::value(hash))
+        .col_expr(entity::file::Column::PepperId, Expr::value(pepper_id))
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
         .exec(database_connection)
         .await
         .map(|_| ())
         .map_err(Error::DatabaseOperationFailed)
 }
 
-/// Store new access log entry to database
+/// Gets all files whose storage key is wrapped with another master key than
+/// the one with given id
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `master_key_id` - Id of the current master key
+///
+/// # Returns
+///
+/// * [`Ok<Vec<entity::file::Model>>`] on success
+/// * [`Err<Error>`] on error
+pub async fn get_files_to_rewrap(
+    database_connection: &DatabaseConnection,
+    master_key_id: i16,
+) -> Result<Vec<entity::file::Model>> {
+    entity::File::find()
+        .filter(entity::file::Column::MasterKeyId.is_not_null())
+        .filter(entity::file::Column::MasterKeyId.ne(master_key_id))
+        .all(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Replaces the wrapped storage key of a file
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the file
+/// * `storage_key` - Storage key, wrapped with another master key
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn update_storage_key(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    storage_key: WrappedStorageKey,
+) -> Result<()> {
+    entity::File::update_many()
+        .col_expr(
+            entity::file::Column::MasterKeyId,
+            Expr::value(storage_key.master_key_id),
+        )
+        .col_expr(
+            entity::file::Column::WrappedStorageKey,
+            Expr::value(storage_key.wrapped_key),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .exec(database_connection)
+        .await
+        .map(|_| ())
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Gets recipient of a file by its public key
 ///
 /// # Arguments
 ///
 /// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the file
+/// * `public_key` - X25519 public key of the recipient
+///
+/// # Returns
+///
+/// * [`Ok<Some<entity::recipient::Model>>`] if recipient exists
+/// * [`Ok<None>`] if file has no recipient with given public key
+/// * [`Err<Error>`] on error
+pub async fn get_recipient(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    public_key: &[u8],
+) -> Result<Option<entity::recipient::Model>> {
+    entity::Recipient::find()
+        .filter(entity::recipient::Column::FileId.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::recipient::Column::PublicKey.eq(public_key))
+        .one(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Sets a new challenge for a recipient, replacing its previous one
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `recipient_id` - Id of the recipient
+/// * `challenge_secret` - Secret of the challenge
+/// * `challenge_until` - Time until the challenge can be answered
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn set_challenge(
+    database_connection: &DatabaseConnection,
+    recipient_id: &[u8],
+    challenge_secret: Vec<u8>,
+    challenge_until: DateTime<Utc>,
+) -> Result<()> {
+    entity::Recipient::update_many()
+        .col_expr(
+            entity::recipient::Column::ChallengeSecret,
+            Expr::value(challenge_secret),
+        )
+        .col_expr(
+            entity::recipient::Column::ChallengeUntil,
+            Expr::value(challenge_until.naive_utc()),
+        )
+        .filter(entity::recipient::Column::Id.eq(recipient_id))
+        .exec(database_connection)
+        .await
+        .map(|_| ())
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Takes the pending challenge of a recipient, so that it can be answered
+///
+/// Taking is atomic: Every challenge can only be taken once, so that it can't
+/// be answered multiple times. Expired challenges can't be taken.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the file
+/// * `public_key` - X25519 public key of the recipient
+///
+/// # Returns
+///
+/// * [`Ok<Some<entity::recipient::Model>>`] containing the recipient along
+///   with its challenge
+/// * [`Ok<None>`] if recipient has no pending challenge
+/// * [`Err<Error>`] on error
+pub async fn take_challenge(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    public_key: &[u8],
+) -> Result<Option<entity::recipient::Model>> {
+    let Some(recipient) = get_recipient(database_connection, file_id, public_key).await? else {
+        return Ok(None);
+    };
+
+    let Some(challenge_secret) = recipient.challenge_secret.clone() else {
+        return Ok(None);
+    };
+
+    let taken = entity::Recipient::update_many()
+        .col_expr(
+            entity::recipient::Column::ChallengeSecret,
+            Expr::value(Option::<Vec<u8>>::None),
+        )
+        .col_expr(
+            entity::recipient::Column::ChallengeUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .filter(entity::recipient::Column::Id.eq(recipient.id.clone()))
+        .filter(entity::recipient::Column::ChallengeSecret.eq(challenge_secret))
+        .filter(entity::recipient::Column::ChallengeUntil.gt(Utc::now().naive_utc()))
+        .exec(database_connection)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?
+        .rows_affected
+        == 1;
+
+    Ok(taken.then_some(recipient))
+}
+
+/// Revokes a file by removing its entry and recording the revocation
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `ip` - Ip of the client revoking the file
+/// * `file_id` - Id of the file to revoke
+///
+/// # Returns
+///
+/// * [`Ok<true>`] if file has been revoked
+/// * [`Ok<false>`] if file doesn't exist (anymore)
+/// * [`Err<Error>`] on error
+pub async fn revoke_file(
+    database_connection: &DatabaseConnection,
+    ip: &str,
+    file_id: &Uuid,
+) -> Result<bool> {
+    let transaction = database_connection
+        .begin()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    let revoked = entity::File::delete_by_id(Vec::<u8>::from(*file_id))
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?
+        .rows_affected
+        == 1;
+
+    if !revoked {
+        return Ok(false);
+    }
+
+    let revocation = entity::revocation::ActiveModel {
+        id: Set(Uuid::new_v4().into()),
+        file_id: Set((*file_id).into()),
+        ip: Set(ip.into()),
+        date_time: Set(Utc::now().naive_utc()),
+    };
+
+    entity::Revocation::insert(revocation)
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    Ok(true)
+}
+
+/// Claims the download of a file
+///
+/// Claiming is atomic: Of concurrent requests claiming the last allowed
+/// download of the same file, only one succeeds. A successful claim is stored
+/// as successful access log entry.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `ip` - Ip of the client claiming the download
+/// * `file_id` - Id of the file to claim
+///
+/// # Returns
+///
+/// * [`Ok<true>`] if download has been claimed
+/// * [`Ok<false>`] if file isn't downloadable (anymore)
+/// * [`Err<Error>`] on error
+pub async fn claim_download(
+    database_connection: &DatabaseConnection,
+    ip: &str,
+    file_id: &Uuid,
+) -> Result<bool> {
+    let transaction = database_connection
+        .begin()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    /* The condition is checked on the updated row itself, so concurrent
+     * updates are serialized by the database and only one of them matches. */
+    let claimed = entity::File::update_many()
+        .col_expr(
+            entity::file::Column::SuccessfulDownloads,
+            Expr::col(entity::file::Column::SuccessfulDownloads).add(1),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::file::Column::DownloadUntil.gte(Utc::now().naive_utc()))
+        .filter(has_downloads_left())
+        .filter(is_not_reserved())
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?
+        .rows_affected
+        == 1;
+
+    if !claimed {
+        return Ok(false);
+    }
+
+    store_access_log(&transaction, ip, file_id, AccessAction::Download, true).await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    Ok(true)
+}
+
+/// Reserves a file for a two-phase download
+///
+/// Reserving is atomic: Of concurrent requests reserving the same file, only
+/// one succeeds. The reservation must be renewed before `reserved_until`, and
+/// then either be finalized via [`finalize_download`] or released via
+/// [`release_download`]. Expired reservations are released implicitly.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the file to reserve
+/// * `reservation_id` - Id of the new reservation
+/// * `reserved_until` - Time until the file is reserved
+///
+/// # Returns
+///
+/// * [`Ok<true>`] if file has been reserved
+/// * [`Ok<false>`] if file isn't downloadable (anymore) or already reserved
+/// * [`Err<Error>`] on error
+pub async fn reserve_download(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    reservation_id: &Uuid,
+    reserved_until: DateTime<Utc>,
+) -> Result<bool> {
+    entity::File::update_many()
+        .col_expr(
+            entity::file::Column::ReservationId,
+            Expr::value(Vec::<u8>::from(*reservation_id)),
+        )
+        .col_expr(
+            entity::file::Column::ReservedUntil,
+            Expr::value(reserved_until.naive_utc()),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::file::Column::DownloadUntil.gte(Utc::now().naive_utc()))
+        .filter(has_downloads_left())
+        .filter(is_not_reserved())
+        .exec(database_connection)
+        .await
+        .map(|result| result.rows_affected == 1)
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Renews a reservation of a two-phase download
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the reserved file
+/// * `reservation_id` - Id of the reservation
+/// * `reserved_until` - New time until the file is reserved
+///
+/// # Returns
+///
+/// * [`Ok<true>`] if reservation has been renewed
+/// * [`Ok<false>`] if reservation doesn't exist (anymore)
+/// * [`Err<Error>`] on error
+pub async fn renew_reservation(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    reservation_id: &Uuid,
+    reserved_until: DateTime<Utc>,
+) -> Result<bool> {
+    entity::File::update_many()
+        .col_expr(
+            entity::file::Column::ReservedUntil,
+            Expr::value(reserved_until.naive_utc()),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::file::Column::ReservationId.eq(Vec::<u8>::from(*reservation_id)))
+        .exec(database_connection)
+        .await
+        .map(|result| result.rows_affected == 1)
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Finalizes a two-phase download after its transfer has completed
+///
+/// The reservation is turned into a successful download, which is stored as
+/// successful access log entry.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `ip` - Ip of the client that downloaded the file
+/// * `file_id` - Id of the reserved file
+/// * `reservation_id` - Id of the reservation
+///
+/// # Returns
+///
+/// * [`Ok<true>`] if download has been finalized
+/// * [`Ok<false>`] if reservation doesn't exist (anymore)
+/// * [`Err<Error>`] on error
+pub async fn finalize_download(
+    database_connection: &DatabaseConnection,
+    ip: &str,
+    file_id: &Uuid,
+    reservation_id: &Uuid,
+) -> Result<bool> {
+    let transaction = database_connection
+        .begin()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    let finalized = entity::File::update_many()
+        .col_expr(
+            entity::file::Column::SuccessfulDownloads,
+            Expr::col(entity::file::Column::SuccessfulDownloads).add(1),
+        )
+        .col_expr(
+            entity::file::Column::ReservationId,
+            Expr::value(Option::<Vec<u8>>::None),
+        )
+        .col_expr(
+            entity::file::Column::ReservedUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::file::Column::ReservationId.eq(Vec::<u8>::from(*reservation_id)))
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?
+        .rows_affected
+        == 1;
+
+    if !finalized {
+        return Ok(false);
+    }
+
+    store_access_log(&transaction, ip, file_id, AccessAction::Download, true).await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    Ok(true)
+}
+
+/// Releases a reservation of a two-phase download, so that the file can be
+/// downloaded again
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file_id` - Id of the reserved file
+/// * `reservation_id` - Id of the reservation
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn release_download(
+    database_connection: &DatabaseConnection,
+    file_id: &Uuid,
+    reservation_id: &Uuid,
+) -> Result<()> {
+    entity::File::update_many()
+        .col_expr(
+            entity::file::Column::ReservationId,
+            Expr::value(Option::<Vec<u8>>::None),
+        )
+        .col_expr(
+            entity::file::Column::ReservedUntil,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+        .filter(entity::file::Column::ReservationId.eq(Vec::<u8>::from(*reservation_id)))
+        .exec(database_connection)
+        .await
+        .map(|_| ())
+        .map_err(Error::DatabaseOperationFailed)
+}
+
+/// Expression matching files that have not been downloaded as often as allowed
+fn has_downloads_left() -> SimpleExpr {
+    Expr::col(entity::file::Column::SuccessfulDownloads)
+        .lt(Expr::col(entity::file::Column::MaxDownloads))
+}
+
+/// Expression matching files that have not been accessed unsuccessfully as
+/// often as allowed
+fn has_attempts_left() -> SimpleExpr {
+    entity::file::Column::FailedAttempts.lt(CONFIGURATION.max_download_tries)
+}
+
+/// Condition matching files that are not reserved by a two-phase download
+fn is_not_reserved() -> Condition {
+    Condition::any()
+        .add(entity::file::Column::ReservedUntil.is_null())
+        .add(entity::file::Column::ReservedUntil.lt(Utc::now().naive_utc()))
+}
+
+/// Store new access log entry to database
+///
+/// Failed attempts are counted on the file as well, in the same transaction.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`ConnectionTrait`] to use
 /// * `ip` - Ip of the client accessing the file
 /// * `file_id` - Id of the file being accessed
+/// * `action` - Kind of access
 /// * `successful` - Whether validation was successful or not
 ///
 /// # Returns
 ///
 /// * [`Ok<()>`] on success
 /// * [`Err<Error>`] on error
-pub async fn store_access_log(
-    database_connection: &DatabaseConnection,
+pub async fn store_access_log<C: ConnectionTrait + TransactionTrait>(
+    database_connection: &C,
     ip: &str,
     file_id: &Uuid,
+    action: AccessAction,
     successful: bool,
 ) -> Result<()> {
+    let transaction = database_connection
+        .begin()
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    if !successful {
+        entity::File::update_many()
+            .col_expr(
+                entity::file::Column::FailedAttempts,
+                Expr::col(entity::file::Column::FailedAttempts).add(1),
+            )
+            .filter(entity::file::Column::Id.eq(Vec::<u8>::from(*file_id)))
+            .exec(&transaction)
+            .await
+            .map_err(Error::DatabaseOperationFailed)?;
+    }
+
     let log = entity::access_log::ActiveModel {
         id: Set(Uuid::new_v4().into()),
         ip: Set(ip.into()),
         file_id: Set((*file_id).into()),
         date_time: Set(Utc::now().naive_utc()),
-        successful: Set(i8::from(successful)),
+        successful: Set(successful),
+        action: Set(action),
     };
 
     entity::AccessLog::insert(log)
-        .exec(database_connection)
+        .exec(&transaction)
+        .await
+        .map_err(Error::DatabaseOperationFailed)?;
+
+    transaction
+        .commit()
         .await
-        .map(|_| ())
         .map_err(Error::DatabaseOperationFailed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ConnectOptions, Database, IntoActiveModel, PaginatorTrait};
+    use std::time::Duration;
+
+    /* The schema is created by the migrations, so they are tested on SQLite
+     * as well */
+    async fn setup_database() -> DatabaseConnection {
+        let path = std::env::temp_dir().join(format!("{}.sqlite", Uuid::new_v4()));
+        let mut connect_options =
+            ConnectOptions::new(format!("sqlite://{}?mode=rwc", path.display()));
+
+        /* Concurrent transactions wait for each other instead of failing */
+        connect_options
+            .map_sqlx_sqlite_opts(|options| options.busy_timeout(Duration::from_secs(30)));
+
+        let database_connection = Database::connect(connect_options).await.unwrap();
+
+        Migrator::up(&database_connection, None).await.unwrap();
+
+        database_connection
+    }
+
+    async fn insert_file(database_connection: &DatabaseConnection, max_downloads: i32) -> Uuid {
+        let id = Uuid::new_v4();
+
+        let file = entity::file::Model {
+            max_downloads,
+            ..test_util::file_model(&id)
+        };
+
+        entity::File::insert(file.into_active_model())
+            .exec(database_connection)
+            .await
+            .unwrap();
+
+        id
+    }
+
+    #[tokio::test]
+    async fn migrations_reverted() {
+        let database_connection = setup_database().await;
+        insert_file(&database_connection, 1).await;
+
+        Migrator::down(&database_connection, None).await.unwrap();
+        Migrator::up(&database_connection, None).await.unwrap();
+
+        assert_eq!(
+            0,
+            entity::File::find()
+                .count(&database_connection)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn parallel_downloads_claimed_once() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 1).await;
+
+        /* All claims are spawned before any of them is awaited, so they run
+         * concurrently */
+        let claims = (0..10)
+            .map(|_| {
+                let database_connection = database_connection.clone();
+                tokio::spawn(
+                    async move { claim_download(&database_connection, "127.0.0.1", &id).await },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut successful_claims = 0;
+        for claim in claims {
+            if claim.await.unwrap().unwrap() {
+                successful_claims += 1;
+            }
+        }
+
+        assert_eq!(1, successful_claims);
+
+        let successful_logs = entity::AccessLog::find()
+            .filter(entity::access_log::Column::Successful.eq(true))
+            .count(&database_connection)
+            .await
+            .unwrap();
+
+        assert_eq!(1, successful_logs);
+        assert!(!claim_download(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn reserved_download_finalized_once() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 1).await;
+        let reserved_until = Utc::now() + Days::new(1);
+
+        let reservation_id = Uuid::new_v4();
+        assert!(
+            reserve_download(&database_connection, &id, &reservation_id, reserved_until)
+                .await
+                .unwrap()
+        );
+
+        /* File can't be claimed by others while being reserved */
+        assert!(
+            !reserve_download(&database_connection, &id, &Uuid::new_v4(), reserved_until)
+                .await
+                .unwrap()
+        );
+        assert!(!claim_download(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+
+        release_download(&database_connection, &id, &reservation_id)
+            .await
+            .unwrap();
+        assert!(
+            !finalize_download(&database_connection, "127.0.0.1", &id, &reservation_id)
+                .await
+                .unwrap()
+        );
+
+        let reservation_id = Uuid::new_v4();
+        assert!(
+            reserve_download(&database_connection, &id, &reservation_id, reserved_until)
+                .await
+                .unwrap()
+        );
+        assert!(
+            finalize_download(&database_connection, "127.0.0.1", &id, &reservation_id)
+                .await
+                .unwrap()
+        );
+
+        let file = entity::File::find_by_id(Vec::<u8>::from(id))
+            .one(&database_connection)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, file.successful_downloads);
+        assert!(file.reservation_id.is_none());
+        assert!(
+            !reserve_download(&database_connection, &id, &Uuid::new_v4(), reserved_until)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_limited_per_file() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 3).await;
+
+        for _ in 0..3 {
+            assert!(claim_download(&database_connection, "127.0.0.1", &id)
+                .await
+                .unwrap());
+        }
+
+        assert!(!claim_download(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn revoked_file_removed_and_recorded() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 1).await;
+
+        assert!(revoke_file(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+        assert!(get_file(&database_connection, &id).await.unwrap().is_none());
+        assert!(!revoke_file(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+
+        let revocations = entity::Revocation::find()
+            .filter(entity::revocation::Column::FileId.eq(Vec::<u8>::from(id)))
+            .count(&database_connection)
+            .await
+            .unwrap();
+
+        assert_eq!(1, revocations);
+    }
+
+    #[tokio::test]
+    async fn storage_usage_summed() {
+        let database_connection = setup_database().await;
+
+        assert_eq!(
+            StorageUsage { files: 0, size: 0 },
+            get_storage_usage(&database_connection).await.unwrap()
+        );
+
+        insert_file(&database_connection, 1).await;
+        insert_file(&database_connection, 1).await;
+
+        assert_eq!(
+            StorageUsage {
+                files: 2,
+                size: 264
+            },
+            get_storage_usage(&database_connection).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_ips_removed() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 1).await;
+
+        store_access_log(
+            &database_connection,
+            "127.0.0.1",
+            &id,
+            AccessAction::Info,
+            false,
+        )
+        .await
+        .unwrap();
+
+        remove_access_logs_before(&database_connection, Utc::now() - TimeDelta::hours(1))
+            .await
+            .unwrap();
+        remove_uploader_ips_before(&database_connection, Utc::now() - TimeDelta::hours(1))
+            .await
+            .unwrap();
+
+        let file = get_file(&database_connection, &id).await.unwrap().unwrap();
+        assert_eq!("127.0.0.1", file.uploader_ip);
+        assert_eq!(
+            1,
+            entity::AccessLog::find()
+                .count(&database_connection)
+                .await
+                .unwrap()
+        );
+
+        remove_access_logs_before(&database_connection, Utc::now() + TimeDelta::hours(1))
+            .await
+            .unwrap();
+        remove_uploader_ips_before(&database_connection, Utc::now() + TimeDelta::hours(1))
+            .await
+            .unwrap();
+
+        let file = get_file(&database_connection, &id).await.unwrap().unwrap();
+        assert_eq!("", file.uploader_ip);
+        assert_eq!(1, file.failed_attempts);
+        assert_eq!(
+            0,
+            entity::AccessLog::find()
+                .count(&database_connection)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn accesses_summarized() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 2).await;
+
+        let file = get_file(&database_connection, &id).await.unwrap().unwrap();
+        assert_eq!(
+            AccessSummary::default(),
+            get_access_summary(&database_connection, &file)
+                .await
+                .unwrap()
+        );
+
+        store_access_log(
+            &database_connection,
+            "127.0.0.1",
+            &id,
+            AccessAction::Info,
+            false,
+        )
+        .await
+        .unwrap();
+        store_access_log(
+            &database_connection,
+            "127.0.0.1",
+            &id,
+            AccessAction::Info,
+            false,
+        )
+        .await
+        .unwrap();
+        store_access_log(
+            &database_connection,
+            "127.0.0.1",
+            &id,
+            AccessAction::Info,
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(claim_download(&database_connection, "127.0.0.1", &id)
+            .await
+            .unwrap());
+
+        let file = get_file(&database_connection, &id).await.unwrap().unwrap();
+        let summary = get_access_summary(&database_connection, &file)
+            .await
+            .unwrap();
+
+        assert_eq!(1, summary.successful_downloads);
+        assert_eq!(2, summary.failed_attempts);
+        assert!(summary.last_downloaded_at.is_some());
+
+        /* Counts don't depend on retained access log entries */
+        remove_access_logs_before(&database_connection, Utc::now() + TimeDelta::seconds(1))
+            .await
+            .unwrap();
+
+        let summary = get_access_summary(&database_connection, &file)
+            .await
+            .unwrap();
+
+        assert_eq!(1, summary.successful_downloads);
+        assert_eq!(2, summary.failed_attempts);
+        assert!(summary.last_downloaded_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn challenge_taken_once() {
+        let database_connection = setup_database().await;
+        let id = insert_file(&database_connection, 1).await;
+        let public_key = vec![1; 32];
+
+        let recipient = entity::recipient::ActiveModel {
+            id: Set(Uuid::new_v4().into()),
+            file_id: Set(id.into()),
+            public_key: Set(public_key.clone()),
+            ephemeral_public_key: Set(vec![2; 32]),
+            wrapped_key: Set(vec![]),
+            challenge_secret: Set(None),
+            challenge_until: Set(None),
+        };
+
+        let recipient_id = entity::Recipient::insert(recipient)
+            .exec(&database_connection)
+            .await
+            .unwrap()
+            .last_insert_id;
+
+        assert!(take_challenge(&database_connection, &id, &public_key)
+            .await
+            .unwrap()
+            .is_none());
+
+        set_challenge(
+            &database_connection,
+            &recipient_id,
+            vec![3; 32],
+            Utc::now() - TimeDelta::seconds(1),
+        )
+        .await
+        .unwrap();
+        assert!(take_challenge(&database_connection, &id, &public_key)
+            .await
+            .unwrap()
+            .is_none());
+
+        set_challenge(
+            &database_connection,
+            &recipient_id,
+            vec![4; 32],
+            Utc::now() + TimeDelta::seconds(60),
+        )
+        .await
+        .unwrap();
+
+        let taken = take_challenge(&database_connection, &id, &public_key)
+            .await
+            .unwrap();
+        assert_eq!(Some(vec![4; 32]), taken.unwrap().challenge_secret);
+        assert!(take_challenge(&database_connection, &id, &public_key)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(take_challenge(&database_connection, &id, &[5; 32])
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
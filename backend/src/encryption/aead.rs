@@ -0,0 +1,631 @@
+//! Encryption based on any AEAD cipher, see [`AeadData`]
+
+use super::cipher::CipherId;
+use super::container::{Header, CONTEXT_BOUND_VERSION, HEADER_SIZE, MAGIC, VERSION};
+use super::definitions::{Context, Encoding, Encryption, StreamEncryption};
+use crate::error::{Error, Result};
+use chacha20poly1305::aead::{
+    consts::U5,
+    generic_array::{typenum::Unsigned, ArrayLength, GenericArray},
+    rand_core::RngCore,
+    stream::{DecryptorBE32, EncryptorBE32},
+    AeadInPlace, Key, KeyInit, Nonce, OsRng, Payload,
+};
+use futures::stream::BoxStream;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use std::marker::PhantomData;
+use std::ops::Sub;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Size of a plain segment of stream encrypted data.
+const SEGMENT_SIZE: usize = 64 * 1024; /* 64 KiB */
+
+/// Number of bytes of the nonce that the STREAM construction uses for its
+/// segment counter and last-segment flag.
+const STREAM_NONCE_OVERHEAD: usize = 5;
+
+/// AEAD cipher that data can be encrypted with via [`AeadData`]
+pub trait Algorithm: AeadInPlace + KeyInit + Send + 'static {
+    /// Id of the cipher, as stored in the container header
+    const CIPHER: CipherId;
+}
+
+/// Container for encrypted data and the necessary information to decrypt it.
+pub struct AeadData<A: Algorithm> {
+    // Version of the container format, 0 for legacy data
+    version: u8,
+    // Nonce for decrypting `content`
+    nonce: Vec<u8>,
+    // Encrypted data
+    content: Vec<u8>,
+    // Cipher of encrypted data
+    algorithm: PhantomData<A>,
+}
+
+impl<A: Algorithm> AeadData<A> {
+    /// Size of the nonce of data that is encrypted as a whole
+    fn nonce_size() -> usize {
+        A::NonceSize::USIZE
+    }
+
+    /// Size of the nonce prefix of stream encrypted data
+    fn stream_nonce_size() -> usize {
+        A::NonceSize::USIZE - STREAM_NONCE_OVERHEAD
+    }
+
+    /// Size of the authentication tag of every encrypted segment
+    fn tag_size() -> usize {
+        A::TagSize::USIZE
+    }
+
+    /// Checks whether given `key` can be used with this cipher
+    fn check_key(key: &[u8]) -> Result<()> {
+        if key.len() != A::key_size() {
+            return Err(Error::InvalidEncryptionData("Invalid key length".into()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<A: Algorithm> Encoding<AeadData<A>> for AeadData<A> {
+    fn encode(mut self) -> Vec<u8> {
+        let mut data = Header::new(A::CIPHER, 0).encode().to_vec();
+        data.append(&mut self.nonce);
+        data.append(&mut self.content);
+        data
+    }
+
+    fn decode<TI: IntoIterator<Item = u8>>(data: TI) -> Result<AeadData<A>> {
+        let mut data = data.into_iter().collect::<Vec<u8>>();
+
+        /* Legacy data consists of nonce and content only */
+        let version = match Header::decode(&data)? {
+            Some(header) => {
+                if header.cipher != A::CIPHER || header.segment_size != 0 {
+                    return Err(Error::InvalidEncryptionData("Unsupported container".into()));
+                }
+
+                data.drain(..HEADER_SIZE);
+                header.version
+            }
+            None => 0,
+        };
+
+        if data.len() < Self::nonce_size() {
+            return Err(Error::InvalidEncryptionData("Data too short".into()));
+        }
+
+        let content = data.split_off(Self::nonce_size());
+
+        Ok(Self {
+            version,
+            nonce: data,
+            content,
+            algorithm: PhantomData,
+        })
+    }
+}
+
+impl<A: Algorithm> Encryption<AeadData<A>> for AeadData<A> {
+    fn encrypt_with_key<TI: IntoIterator<Item = u8>>(
+        plain: TI,
+        key: &[u8],
+        context: &Context,
+    ) -> Result<AeadData<A>> {
+        Self::check_key(key)?;
+
+        let cipher = A::new(Key::<A>::from_slice(key));
+        let nonce = A::generate_nonce(&mut OsRng);
+
+        let mut content = plain.into_iter().collect::<Vec<u8>>();
+
+        cipher
+            .encrypt_in_place(&nonce, &context.associated_data(), &mut content)
+            .map_err(|_| Error::EncryptionFailed)?;
+
+        Ok(AeadData {
+            version: VERSION,
+            nonce: nonce.to_vec(),
+            content,
+            algorithm: PhantomData,
+        })
+    }
+
+    fn decrypt(mut self, key: &[u8], context: &Context) -> Result<Vec<u8>> {
+        Self::check_key(key)?;
+
+        let cipher = A::new(Key::<A>::from_slice(key));
+        let nonce = Nonce::<A>::from_slice(&self.nonce);
+
+        cipher
+            .decrypt_in_place(
+                nonce,
+                &associated_data(self.version, context),
+                &mut self.content,
+            )
+            .map_err(|_| Error::EncryptionFailed)?;
+
+        Ok(self.content)
+    }
+}
+
+impl<A: Algorithm> StreamEncryption for AeadData<A>
+where
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    fn encrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        plain: R,
+        context: Context,
+    ) -> (
+        impl Stream<Item = Result<Vec<u8>>> + Send + 'static,
+        Vec<u8>,
+    ) {
+        let key = A::generate_key(&mut OsRng);
+
+        let mut nonce = vec![0u8; Self::stream_nonce_size()];
+        OsRng.fill_bytes(&mut nonce);
+
+        let encryptor = EncryptorBE32::<A>::new(&key, GenericArray::from_slice(&nonce));
+
+        /* Each segment is read with one additional byte. If this byte is
+         * filled, we know that the segment is not the last one. */
+        let segments = stream::try_unfold(
+            (plain, Some(encryptor), vec![], context.associated_data()),
+            |(mut plain, encryptor, mut segment, aad)| async move {
+                let Some(mut encryptor) = encryptor else {
+                    return Ok(None);
+                };
+
+                (&mut plain)
+                    .take((SEGMENT_SIZE + 1 - segment.len()) as u64)
+                    .read_to_end(&mut segment)
+                    .await
+                    .map_err(Error::ReadingDataFailed)?;
+
+                if segment.len() <= SEGMENT_SIZE {
+                    let encrypted = encryptor
+                        .encrypt_last(payload(&segment, &aad))
+                        .map_err(|_| Error::EncryptionFailed)?;
+
+                    return Ok(Some((encrypted, (plain, None, vec![], aad))));
+                }
+
+                let next_segment = segment.split_off(SEGMENT_SIZE);
+
+                let encrypted = encryptor
+                    .encrypt_next(payload(&segment, &aad))
+                    .map_err(|_| Error::EncryptionFailed)?;
+
+                Ok(Some((
+                    encrypted,
+                    (plain, Some(encryptor), next_segment, aad),
+                )))
+            },
+        );
+
+        let mut prefix = Header::new(A::CIPHER, SEGMENT_SIZE as u32)
+            .encode()
+            .to_vec();
+        prefix.extend_from_slice(&nonce);
+
+        let encrypted = stream::once(async move { Ok(prefix) }).chain(segments);
+
+        (encrypted, key.to_vec())
+    }
+
+    fn decrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        mut encrypted: R,
+        key: &[u8],
+        context: Context,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>>> + Send + 'static> {
+        Self::check_key(key)?;
+
+        let key = Key::<A>::clone_from_slice(key);
+
+        let decrypted = stream::once(async move {
+            let mut header = [0u8; HEADER_SIZE];
+            read_exact(&mut encrypted, &mut header[..MAGIC.len()]).await?;
+
+            if !header.starts_with(&MAGIC) {
+                return Self::decrypt_legacy(
+                    header[..MAGIC.len()].to_vec(),
+                    encrypted,
+                    &key,
+                    &context,
+                )
+                .await;
+            }
+
+            read_exact(&mut encrypted, &mut header[MAGIC.len()..]).await?;
+
+            let header = Header::decode(&header)?
+                .ok_or(Error::InvalidEncryptionData("Header missing".into()))?;
+
+            if header.cipher != A::CIPHER || header.segment_size == 0 {
+                return Err(Error::InvalidEncryptionData("Unsupported container".into()));
+            }
+
+            let mut nonce = vec![0u8; Self::stream_nonce_size()];
+            read_exact(&mut encrypted, &mut nonce).await?;
+
+            Ok(decrypt_segments::<A>(
+                Box::new(encrypted),
+                DecryptorBE32::<A>::new(&key, GenericArray::from_slice(&nonce)),
+                header.segment_size as usize + Self::tag_size(),
+                associated_data(header.version, &context),
+            ))
+        })
+        .try_flatten();
+
+        Ok(decrypted)
+    }
+}
+
+impl<A: Algorithm> AeadData<A> {
+    /// Decrypts legacy data without container header, which isn't bound to
+    /// any context
+    ///
+    /// Data uploaded before content was encrypted in segments consists of
+    /// nonce and content, encrypted as a whole. It can only be authenticated
+    /// as a whole, so it's read completely before it's decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data that has already been read from `encrypted`
+    /// * `encrypted` - Reader of the remaining data
+    /// * `key` - Key to decrypt with
+    /// * `context` - Context of the data, which is ignored as data is legacy
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<BoxStream>`] on success, containing stream of decrypted data
+    /// * [`Err<Error>`] on error
+    async fn decrypt_legacy<R: AsyncRead + Unpin + Send + 'static>(
+        mut data: Vec<u8>,
+        mut encrypted: R,
+        key: &Key<A>,
+        context: &Context,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>>>> {
+        /* Legacy data has been stored as a whole, so it fits into memory */
+        encrypted
+            .read_to_end(&mut data)
+            .await
+            .map_err(Error::ReadingDataFailed)?;
+
+        let plain = Self::decode(data)?.decrypt(key, context)?;
+
+        Ok(stream::once(async move { Ok(plain) }).boxed())
+    }
+}
+
+/// Decrypts the segments of stream encrypted data while they are being read
+///
+/// Same as on encryption, each segment is read with one additional byte to
+/// find out whether it's the last one.
+///
+/// # Arguments
+///
+/// * `encrypted` - Reader of the encrypted segments
+/// * `decryptor` - Decryptor of the segments
+/// * `encrypted_segment_size` - Size of an encrypted segment, including tag
+/// * `aad` - Associated data of every segment
+///
+/// # Returns
+///
+/// * Stream of decrypted segments
+fn decrypt_segments<A: Algorithm>(
+    encrypted: Box<dyn AsyncRead + Unpin + Send>,
+    decryptor: DecryptorBE32<A>,
+    encrypted_segment_size: usize,
+    aad: Vec<u8>,
+) -> BoxStream<'static, Result<Vec<u8>>>
+where
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    stream::try_unfold(
+        (encrypted, Some(decryptor), vec![], aad),
+        move |(mut encrypted, decryptor, mut segment, aad)| async move {
+            let Some(mut decryptor) = decryptor else {
+                return Ok(None);
+            };
+
+            (&mut encrypted)
+                .take((encrypted_segment_size + 1 - segment.len()) as u64)
+                .read_to_end(&mut segment)
+                .await
+                .map_err(Error::ReadingDataFailed)?;
+
+            if segment.len() <= encrypted_segment_size {
+                let decrypted = decryptor
+                    .decrypt_last(payload(&segment, &aad))
+                    .map_err(|_| Error::DecryptionFailed)?;
+
+                return Ok(Some((decrypted, (encrypted, None, vec![], aad))));
+            }
+
+            let next_segment = segment.split_off(encrypted_segment_size);
+
+            let decrypted = decryptor
+                .decrypt_next(payload(&segment, &aad))
+                .map_err(|_| Error::DecryptionFailed)?;
+
+            Ok(Some((
+                decrypted,
+                (encrypted, Some(decryptor), next_segment, aad),
+            )))
+        },
+    )
+    .boxed()
+}
+
+/// Returns the associated data that data of given container `version` has
+/// been encrypted with. Data of older versions isn't bound to any context.
+fn associated_data(version: u8, context: &Context) -> Vec<u8> {
+    if version >= CONTEXT_BOUND_VERSION {
+        context.associated_data()
+    } else {
+        vec![]
+    }
+}
+
+/// Creates payload of a segment and its associated data
+fn payload<'a>(segment: &'a [u8], aad: &'a [u8]) -> Payload<'a, 'a> {
+    Payload { msg: segment, aad }
+}
+
+/// Reads exactly enough data from `reader` to fill `buffer`
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error or if `reader` ends before `buffer` is filled
+async fn read_exact<R: AsyncRead + Unpin>(reader: &mut R, buffer: &mut [u8]) -> Result<()> {
+    reader
+        .read_exact(buffer)
+        .await
+        .map(|_| ())
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => {
+                Error::InvalidEncryptionData("Data too short".into())
+            }
+            _ => Error::ReadingDataFailed(error),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm_siv::Aes256GcmSiv;
+    use chacha20poly1305::aead::AeadCore;
+    use chacha20poly1305::XChaCha20Poly1305;
+    use uuid::Uuid;
+
+    fn context() -> Context {
+        Context::content(Uuid::from_u128(1))
+    }
+
+    macro_rules! cipher_tests {
+        ($module:ident, $cipher:ty) => {
+            mod $module {
+                use super::*;
+
+                type Data = AeadData<$cipher>;
+
+                /// Size of an encrypted segment of stream encrypted data
+                const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + 16;
+
+                /// Size of everything in front of the first segment
+                fn prefix_size() -> usize {
+                    HEADER_SIZE + Data::stream_nonce_size()
+                }
+
+                async fn encrypted_stream_length(plain_length: usize) -> usize {
+                    let plain = std::io::Cursor::new(vec![7u8; plain_length]);
+                    let (encrypted, key) = Data::encrypt_stream(plain, context());
+
+                    assert_eq!(32, key.len());
+
+                    encrypted
+                        .try_collect::<Vec<Vec<u8>>>()
+                        .await
+                        .unwrap()
+                        .iter()
+                        .map(Vec::len)
+                        .sum()
+                }
+
+                async fn encrypt_to_vec(plain: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+                    let (encrypted, key) =
+                        Data::encrypt_stream(std::io::Cursor::new(plain), context());
+                    let encrypted = encrypted.try_concat().await.unwrap();
+
+                    (encrypted, key)
+                }
+
+                async fn decrypt_to_vec(
+                    encrypted: Vec<u8>,
+                    key: &[u8],
+                    context: Context,
+                ) -> Result<Vec<u8>> {
+                    Data::decrypt_stream(std::io::Cursor::new(encrypted), key, context)?
+                        .try_concat()
+                        .await
+                }
+
+                #[tokio::test]
+                async fn stream_encrypted_in_segments() {
+                    /* Header + nonce prefix + plain data + one 16 byte tag per segment */
+                    assert_eq!(prefix_size() + 16, encrypted_stream_length(0).await);
+                    assert_eq!(prefix_size() + 1 + 16, encrypted_stream_length(1).await);
+                    assert_eq!(
+                        prefix_size() + SEGMENT_SIZE + 16,
+                        encrypted_stream_length(SEGMENT_SIZE).await
+                    );
+                    assert_eq!(
+                        prefix_size() + SEGMENT_SIZE + 1 + 2 * 16,
+                        encrypted_stream_length(SEGMENT_SIZE + 1).await
+                    );
+                    assert_eq!(
+                        prefix_size() + 3 * SEGMENT_SIZE + 3 * 16,
+                        encrypted_stream_length(3 * SEGMENT_SIZE).await
+                    );
+                }
+
+                #[tokio::test]
+                async fn stream_decrypted() {
+                    for plain_length in
+                        [0, 1, SEGMENT_SIZE, SEGMENT_SIZE + 1, 3 * SEGMENT_SIZE + 17]
+                    {
+                        let plain = (0..plain_length).map(|i| i as u8).collect::<Vec<u8>>();
+                        let (encrypted, key) = encrypt_to_vec(plain.clone()).await;
+
+                        assert_eq!(
+                            plain,
+                            decrypt_to_vec(encrypted, &key, context()).await.unwrap()
+                        );
+                    }
+                }
+
+                #[tokio::test]
+                async fn tampered_stream_not_decrypted() {
+                    let (encrypted, key) = encrypt_to_vec(vec![1; 2 * SEGMENT_SIZE]).await;
+
+                    let mut tampered = encrypted.clone();
+                    tampered[prefix_size() + 1] ^= 1;
+                    assert!(decrypt_to_vec(tampered, &key, context()).await.is_err());
+
+                    let truncated = encrypted[..prefix_size() + ENCRYPTED_SEGMENT_SIZE].to_vec();
+                    assert!(decrypt_to_vec(truncated, &key, context()).await.is_err());
+
+                    assert!(decrypt_to_vec(encrypted.clone(), &[0; 32], context())
+                        .await
+                        .is_err());
+                    assert!(decrypt_to_vec(encrypted[..10].to_vec(), &key, context())
+                        .await
+                        .is_err());
+                }
+
+                #[tokio::test]
+                async fn stream_bound_to_context() {
+                    let (encrypted, key) = encrypt_to_vec(vec![1; 10]).await;
+
+                    let other_file = Context::content(Uuid::from_u128(2));
+                    assert!(decrypt_to_vec(encrypted.clone(), &key, other_file)
+                        .await
+                        .is_err());
+
+                    let other_purpose = Context::metadata(context().file_id);
+                    assert!(decrypt_to_vec(encrypted, &key, other_purpose)
+                        .await
+                        .is_err());
+                }
+
+                #[tokio::test]
+                async fn unsupported_stream_not_decrypted() {
+                    let (mut encrypted, key) = encrypt_to_vec(vec![3; 10]).await;
+
+                    encrypted[..HEADER_SIZE]
+                        .copy_from_slice(&Header::new(<$cipher>::CIPHER, 0).encode());
+                    assert!(decrypt_to_vec(encrypted, &key, context()).await.is_err());
+                }
+
+                #[test]
+                fn data_bound_to_context() {
+                    let key = [7; 32];
+                    let context = Context::metadata(Uuid::from_u128(1));
+                    let encrypted = Data::encrypt_with_key(vec![1, 2, 3], &key, &context)
+                        .unwrap()
+                        .encode();
+
+                    assert!(encrypted.starts_with(&MAGIC));
+
+                    let decoded = Data::decode(encrypted.clone()).unwrap();
+                    assert_eq!(vec![1, 2, 3], decoded.decrypt(&key, &context).unwrap());
+
+                    let decoded = Data::decode(encrypted).unwrap();
+                    let other_file = Context::metadata(Uuid::from_u128(2));
+                    assert!(decoded.decrypt(&key, &other_file).is_err());
+                }
+            }
+        };
+    }
+
+    cipher_tests!(xchacha20poly1305, XChaCha20Poly1305);
+    cipher_tests!(aes256gcmsiv, Aes256GcmSiv);
+
+    #[tokio::test]
+    async fn other_cipher_not_decrypted() {
+        let (encrypted, key) = AeadData::<Aes256GcmSiv>::encrypt_stream(
+            std::io::Cursor::new(vec![1, 2, 3]),
+            context(),
+        );
+        let encrypted = encrypted.try_concat().await.unwrap();
+
+        let decrypted = AeadData::<XChaCha20Poly1305>::decrypt_stream(
+            std::io::Cursor::new(encrypted),
+            &key,
+            context(),
+        )
+        .unwrap()
+        .try_concat()
+        .await;
+        assert!(decrypted.is_err());
+    }
+
+    #[tokio::test]
+    async fn baseline_content_streamed() {
+        /* Baseline content consists of nonce and content encrypted as a whole
+         * without associated data */
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+
+        for plain_length in [0, 3, 2 * SEGMENT_SIZE + 5] {
+            let plain = (0..plain_length).map(|i| i as u8).collect::<Vec<u8>>();
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let mut content = plain.clone();
+            XChaCha20Poly1305::new(&key)
+                .encrypt_in_place(&nonce, &[], &mut content)
+                .unwrap();
+
+            let baseline = [nonce.to_vec(), content].concat();
+            let decrypted = AeadData::<XChaCha20Poly1305>::decrypt_stream(
+                std::io::Cursor::new(baseline.clone()),
+                &key,
+                context(),
+            )
+            .unwrap()
+            .try_concat()
+            .await
+            .unwrap();
+            assert_eq!(plain, decrypted);
+
+            let decrypted = AeadData::<XChaCha20Poly1305>::decrypt_stream(
+                std::io::Cursor::new(baseline),
+                &[0; 32],
+                context(),
+            )
+            .unwrap()
+            .try_concat()
+            .await;
+            assert!(decrypted.is_err());
+        }
+    }
+
+    #[test]
+    fn legacy_data_decoded() {
+        /* Legacy data consists of nonce and content without associated data */
+        let key = [7; 32];
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut content = vec![1, 2, 3];
+        XChaCha20Poly1305::new(Key::<XChaCha20Poly1305>::from_slice(&key))
+            .encrypt_in_place(&nonce, &[], &mut content)
+            .unwrap();
+
+        let legacy =
+            AeadData::<XChaCha20Poly1305>::decode([nonce.to_vec(), content].concat()).unwrap();
+        let context = Context::metadata(Uuid::from_u128(1));
+        assert_eq!(vec![1, 2, 3], legacy.decrypt(&key, &context).unwrap());
+    }
+}
@@ -0,0 +1,12 @@
+//! AES-256-GCM-SIV encryption scheme
+
+use super::aead::{AeadData, Algorithm};
+use super::cipher::CipherId;
+use aes_gcm_siv::Aes256GcmSiv;
+
+impl Algorithm for Aes256GcmSiv {
+    const CIPHER: CipherId = CipherId::Aes256GcmSiv;
+}
+
+/// Container for data encrypted with AES-256-GCM-SIV
+pub type Aes256GcmSivData = AeadData<Aes256GcmSiv>;
@@ -0,0 +1,158 @@
+//! Ciphers that data can be encrypted with, selectable at runtime
+
+use super::aes256gcmsiv::Aes256GcmSivData;
+use super::definitions::{Context, Encoding, Encryption, StreamEncryption};
+use super::xchacha20poly1305::XChaCha20Poly1305Data;
+use crate::error::{Error, Result};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::io::AsyncRead;
+
+/// Id of the cipher that data is encrypted with. The id is stored in the
+/// container header of encrypted data as well as with every file.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum CipherId {
+    /// XChaCha20-Poly1305, used for all files before ciphers were selectable
+    #[default]
+    XChaCha20Poly1305 = 1,
+    /// AES-256-GCM-SIV
+    Aes256GcmSiv = 2,
+}
+
+impl TryFrom<u8> for CipherId {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            1 => Ok(Self::XChaCha20Poly1305),
+            2 => Ok(Self::Aes256GcmSiv),
+            _ => Err(Error::InvalidEncryptionData(format!(
+                "Unknown cipher id {value}"
+            ))),
+        }
+    }
+}
+
+/// Conversion of the id as it's stored with every file
+impl TryFrom<i16> for CipherId {
+    type Error = Error;
+
+    fn try_from(value: i16) -> Result<Self> {
+        u8::try_from(value)
+            .map_err(|_| Error::InvalidEncryptionData(format!("Unknown cipher id {value}")))
+            .and_then(Self::try_from)
+    }
+}
+
+impl CipherId {
+    /// Encrypts data read from `plain` segment by segment with this cipher.
+    /// See [`StreamEncryption::encrypt_stream`].
+    ///
+    /// # Arguments
+    ///
+    /// * `plain` - Reader of plain data to encrypt
+    /// * `context` - Context to bind encrypted data to
+    ///
+    /// # Returns
+    ///
+    /// * (Stream of encoded, encrypted data, decryption key)
+    pub fn encrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        self,
+        plain: R,
+        context: Context,
+    ) -> (BoxStream<'static, Result<Vec<u8>>>, Vec<u8>) {
+        match self {
+            Self::XChaCha20Poly1305 => {
+                let (encrypted, key) = XChaCha20Poly1305Data::encrypt_stream(plain, context);
+                (encrypted.boxed(), key)
+            }
+            Self::Aes256GcmSiv => {
+                let (encrypted, key) = Aes256GcmSivData::encrypt_stream(plain, context);
+                (encrypted.boxed(), key)
+            }
+        }
+    }
+
+    /// Decrypts data read from `encrypted` segment by segment with this
+    /// cipher. See [`StreamEncryption::decrypt_stream`].
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted` - Reader of encoded, encrypted data
+    /// * `key` - Decryption key for this encrypted data
+    /// * `context` - Context that encrypted data must be bound to
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<BoxStream>`] on success, containing stream of decrypted data
+    /// * [`Err<Error>`] on error
+    pub fn decrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        self,
+        encrypted: R,
+        key: &[u8],
+        context: Context,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>>>> {
+        Ok(match self {
+            Self::XChaCha20Poly1305 => {
+                XChaCha20Poly1305Data::decrypt_stream(encrypted, key, context)?.boxed()
+            }
+            Self::Aes256GcmSiv => {
+                Aes256GcmSivData::decrypt_stream(encrypted, key, context)?.boxed()
+            }
+        })
+    }
+
+    /// Encrypts `plain` data as a whole with this cipher and encodes it, so
+    /// that it can be stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `plain` - Plain data to encrypt
+    /// * `key` - Predefined key to use
+    /// * `context` - Context to bind encrypted data to
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Vec<u8>>`] on success, containing encoded, encrypted data
+    /// * [`Err<Error>`] on error
+    pub fn encrypt_with_key<TI: IntoIterator<Item = u8>>(
+        self,
+        plain: TI,
+        key: &[u8],
+        context: &Context,
+    ) -> Result<Vec<u8>> {
+        match self {
+            Self::XChaCha20Poly1305 => {
+                XChaCha20Poly1305Data::encrypt_with_key(plain, key, context).map(Encoding::encode)
+            }
+            Self::Aes256GcmSiv => {
+                Aes256GcmSivData::encrypt_with_key(plain, key, context).map(Encoding::encode)
+            }
+        }
+    }
+
+    /// Decodes and decrypts data that has been encrypted as a whole with this
+    /// cipher.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded` - Encoded, encrypted data
+    /// * `key` - Decryption key for this encrypted data
+    /// * `context` - Context that encrypted data must be bound to
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Vec<u8>>`] on success with decrypted data
+    /// * [`Err<Error>`] on error
+    pub fn decrypt(self, encoded: Vec<u8>, key: &[u8], context: &Context) -> Result<Vec<u8>> {
+        match self {
+            Self::XChaCha20Poly1305 => {
+                XChaCha20Poly1305Data::decode(encoded).and_then(|data| data.decrypt(key, context))
+            }
+            Self::Aes256GcmSiv => {
+                Aes256GcmSivData::decode(encoded).and_then(|data| data.decrypt(key, context))
+            }
+        }
+    }
+}
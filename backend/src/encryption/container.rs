@@ -0,0 +1,148 @@
+//! Self-describing container format of encrypted data
+//!
+//! Encrypted data starts with a [`Header`] that describes how it has been
+//! encrypted, so that the format can evolve without breaking stored data.
+//! Data that has been stored before the header existed doesn't start with
+//! [`MAGIC`] and is read as legacy, headerless data.
+
+use super::cipher::CipherId;
+use crate::error::{Error, Result};
+
+/// Magic bytes at the start of every container
+pub const MAGIC: [u8; 4] = *b"TCHC";
+
+/// Current version of the container format. Data of version 2 onwards is
+/// bound to its [`super::Context`] via associated data, while data of version
+/// 1 and legacy data are not.
+pub const VERSION: u8 = 2;
+
+/// Version of the container format that binds data to its context
+pub const CONTEXT_BOUND_VERSION: u8 = 2;
+
+/// Size of an encoded [`Header`], including [`MAGIC`]
+pub const HEADER_SIZE: usize = 10;
+
+/// Max size of plain segments that is accepted on decoding. Segments are held
+/// in memory completely, so this limits memory usage per request.
+pub const MAX_SEGMENT_SIZE: u32 = 16 * 1024 * 1024; /* 16 MiB */
+
+/// Header of a container, describing how its data has been encrypted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Version of the container format
+    pub version: u8,
+    /// Cipher that data is encrypted with
+    pub cipher: CipherId,
+    /// Size of plain segments, or 0 if data is encrypted as a whole
+    pub segment_size: u32,
+}
+
+impl Header {
+    /// Creates header of the current version
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - Cipher that data is encrypted with
+    /// * `segment_size` - Size of plain segments, or 0 if data is encrypted
+    ///   as a whole
+    pub fn new(cipher: CipherId, segment_size: u32) -> Self {
+        Self {
+            version: VERSION,
+            cipher,
+            segment_size,
+        }
+    }
+
+    /// Encodes header so that it can be prepended to encrypted data
+    ///
+    /// # Returns
+    ///
+    /// * Encoded header
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = self.version;
+        header[5] = self.cipher as u8;
+        header[6..].copy_from_slice(&self.segment_size.to_be_bytes());
+        header
+    }
+
+    /// Decodes header from the start of given `data`
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to decode header of
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Some<Header>>`] if `data` starts with a valid header
+    /// * [`Ok<None>`] if `data` doesn't start with [`MAGIC`], so it's legacy,
+    ///   headerless data
+    /// * [`Err<Error>`] if header is invalid or not supported
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        if !data.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+
+        if data.len() < HEADER_SIZE {
+            return Err(Error::InvalidEncryptionData("Header too short".into()));
+        }
+
+        let version = data[4];
+        if !(1..=VERSION).contains(&version) {
+            return Err(Error::InvalidEncryptionData(format!(
+                "Unsupported version {version}"
+            )));
+        }
+
+        let cipher = CipherId::try_from(data[5])?;
+
+        let segment_size = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+        if segment_size > MAX_SEGMENT_SIZE {
+            return Err(Error::InvalidEncryptionData(format!(
+                "Segment size {segment_size} too large"
+            )));
+        }
+
+        Ok(Some(Self {
+            version,
+            cipher,
+            segment_size,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_decoded() {
+        let header = Header::new(CipherId::XChaCha20Poly1305, 64 * 1024);
+        let mut data = header.encode().to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+
+        assert_eq!(Some(header), Header::decode(&data).unwrap());
+        assert_eq!(None, Header::decode(&[1; 32]).unwrap());
+    }
+
+    #[test]
+    fn invalid_header_not_decoded() {
+        let header = Header::new(CipherId::XChaCha20Poly1305, 0).encode();
+
+        assert!(Header::decode(&header[..HEADER_SIZE - 1]).is_err());
+
+        let mut unknown_version = header;
+        unknown_version[4] = VERSION + 1;
+        assert!(Header::decode(&unknown_version).is_err());
+        unknown_version[4] = 0;
+        assert!(Header::decode(&unknown_version).is_err());
+
+        let mut unknown_cipher = header;
+        unknown_cipher[5] = 0;
+        assert!(Header::decode(&unknown_cipher).is_err());
+
+        let too_large = Header::new(CipherId::XChaCha20Poly1305, MAX_SEGMENT_SIZE + 1).encode();
+        assert!(Header::decode(&too_large).is_err());
+    }
+}
@@ -1,4 +1,92 @@
 use crate::error::Result;
+use futures::Stream;
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+/// Purpose of encrypted data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Purpose {
+    /// Content of a file
+    Content,
+    /// Metadata of a file, like its name
+    Metadata,
+    /// Encryption key of a file, wrapped with a key derived from a passphrase
+    Key,
+    /// Stored data of a file, encrypted with its storage key
+    Storage,
+    /// Storage key of a file, wrapped with a master key
+    StorageKey,
+}
+
+/// Context that encrypted data is bound to.
+/// The context is authenticated on decryption, so encrypted data can't be
+/// swapped between files or purposes unnoticed, even if they share a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Context {
+    /// Id of the file the data belongs to
+    pub file_id: Uuid,
+    /// Purpose of the data
+    pub purpose: Purpose,
+}
+
+impl Context {
+    /// Creates context of the content of file with given id
+    pub fn content(file_id: Uuid) -> Self {
+        Self {
+            file_id,
+            purpose: Purpose::Content,
+        }
+    }
+
+    /// Creates context of the metadata of file with given id
+    pub fn metadata(file_id: Uuid) -> Self {
+        Self {
+            file_id,
+            purpose: Purpose::Metadata,
+        }
+    }
+
+    /// Creates context of the wrapped key of file with given id
+    pub fn key(file_id: Uuid) -> Self {
+        Self {
+            file_id,
+            purpose: Purpose::Key,
+        }
+    }
+
+    /// Creates context of the stored data of file with given id
+    pub fn storage(file_id: Uuid) -> Self {
+        Self {
+            file_id,
+            purpose: Purpose::Storage,
+        }
+    }
+
+    /// Creates context of the wrapped storage key of file with given id
+    pub fn storage_key(file_id: Uuid) -> Self {
+        Self {
+            file_id,
+            purpose: Purpose::StorageKey,
+        }
+    }
+
+    /// Returns the associated data that represents this context
+    ///
+    /// # Returns
+    ///
+    /// * Purpose label, followed by the file id
+    pub fn associated_data(&self) -> Vec<u8> {
+        let label: &[u8] = match self.purpose {
+            Purpose::Content => b"content",
+            Purpose::Metadata => b"metadata",
+            Purpose::Key => b"key",
+            Purpose::Storage => b"storage",
+            Purpose::StorageKey => b"storage key",
+        };
+
+        [label, self.file_id.as_bytes()].concat()
+    }
+}
 
 /// Provides functions to make encrypted data store-able.
 /// Handles encoding and decoding of encrypted data including things like nonce.
@@ -30,42 +118,80 @@ pub trait Encoding<T> {
 
 /// Provides functions to create encrypted data and decrypt it back.
 pub trait Encryption<T> {
-    /// Encrypts given data.
-    /// Encrypts plain data and returns encryption-data and the key as a tuple.
+    // Encrypts plain data with given key and returns encryption-data.
+    ///
     ///
     /// # Arguments
     ///
     /// * `plain` - Plain data to encrypt
+    /// * `key` - Predefined key to use
+    /// * `context` - Context to bind encrypted data to
     ///
     /// # Returns
     ///
-    /// * [`Ok<(T, Vec<u8>)>`] on success, containing (Self, decryption key)
+    /// * [`Ok<Vec<u8>>`] on success, containing encrypted data
     /// * [`Err<Error>`] on error
-    fn encrypt<TI: IntoIterator<Item = u8>>(plain: TI) -> Result<(T, Vec<u8>)>;
+    fn encrypt_with_key<TI: IntoIterator<Item = u8>>(
+        plain: TI,
+        key: &[u8],
+        context: &Context,
+    ) -> Result<T>;
 
-    // Encrypts plain data with given key and returns encryption-data.
-    ///
+    /// Decrypts data with given key.
     ///
     /// # Arguments
     ///
-    /// * `plain` - Plain data to encrypt
-    /// * `key` - Predefined key to use
+    /// * `key` - Decryption key for this encrypted data
+    /// * `context` - Context that encrypted data must be bound to
     ///
     /// # Returns
     ///
-    /// * [`Ok<Vec<u8>>`] on success, containing encrypted data
+    /// * [`Ok<Vec<u8>>`] on success with decrypted data
     /// * [`Err<Error>`] on error
-    fn encrypt_with_key<TI: IntoIterator<Item = u8>>(plain: TI, key: &[u8]) -> Result<T>;
+    fn decrypt(self, key: &[u8], context: &Context) -> Result<Vec<u8>>;
+}
 
-    /// Decrypts data with given key.
+/// Provides functions to encrypt data as a stream of fixed-size segments.
+/// Data never has to be held in memory completely, so memory usage stays
+/// constant regardless of the size of the data.
+pub trait StreamEncryption {
+    /// Encrypts data read from `plain` segment by segment.
+    /// Returns a stream of encoded, encrypted data and the key as a tuple.
+    /// Data is only read from `plain` while the returned stream is polled.
     ///
     /// # Arguments
     ///
+    /// * `plain` - Reader of plain data to encrypt
+    /// * `context` - Context to bind encrypted data to
+    ///
+    /// # Returns
+    ///
+    /// * (Stream of encoded, encrypted data, decryption key)
+    fn encrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        plain: R,
+        context: Context,
+    ) -> (
+        impl Stream<Item = Result<Vec<u8>>> + Send + 'static,
+        Vec<u8>,
+    );
+
+    /// Decrypts data read from `encrypted` segment by segment.
+    /// Every segment is authenticated before it is returned. Data is only
+    /// read from `encrypted` while the returned stream is polled.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted` - Reader of encoded, encrypted data
     /// * `key` - Decryption key for this encrypted data
+    /// * `context` - Context that encrypted data must be bound to
     ///
     /// # Returns
     ///
-    /// * [`Ok<Vec<u8>>`] on success with decrypted data
+    /// * [`Ok<Stream>`] on success, containing stream of decrypted data
     /// * [`Err<Error>`] on error
-    fn decrypt(self, key: &[u8]) -> Result<Vec<u8>>;
+    fn decrypt_stream<R: AsyncRead + Unpin + Send + 'static>(
+        encrypted: R,
+        key: &[u8],
+        context: Context,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>>> + Send + 'static>;
 }
@@ -0,0 +1,188 @@
+//! Master keys that stored data of files is additionally encrypted with
+//!
+//! If master keys are configured, the stored data of every new file is
+//! encrypted a second time with a random storage key (envelope encryption).
+//! The storage key is wrapped with the current master key and stored along
+//! with the id of that master key. Master keys are never stored in the
+//! database, so stored data can't be decrypted with a leaked file key alone.
+//!
+//! To rotate master keys, a new master key with a higher id is added and the
+//! storage keys of all files are re-wrapped with it. Stored data doesn't have
+//! to be touched for that.
+
+use super::cipher::CipherId;
+use super::definitions::Context;
+use crate::configuration::CONFIGURATION;
+use crate::error::{Error, Result};
+use crate::util;
+use uuid::Uuid;
+
+/// Length of master keys in bytes
+const KEY_LENGTH: usize = 32;
+
+/// Server-held key that storage keys of files are wrapped with
+pub struct MasterKey {
+    /// Id of the master key, stored along with every storage key it wraps
+    pub id: i16,
+    /// Secret key
+    pub key: Vec<u8>,
+}
+
+/// Storage key of a file, wrapped with a master key
+pub struct WrappedStorageKey {
+    /// Id of the master key that the storage key is wrapped with
+    pub master_key_id: i16,
+    /// Encoded, encrypted storage key
+    pub wrapped_key: Vec<u8>,
+}
+
+impl MasterKey {
+    /// Wraps `storage_key` of a file with this master key
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_key` - Storage key of the file
+    /// * `id` - File id
+    /// * `cipher` - Cipher the file is encrypted with
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<WrappedStorageKey>`] on success
+    /// * [`Err<Error>`] on error
+    pub fn wrap(
+        &self,
+        storage_key: &[u8],
+        id: &Uuid,
+        cipher: CipherId,
+    ) -> Result<WrappedStorageKey> {
+        let wrapped_key = cipher.encrypt_with_key(
+            storage_key.iter().copied(),
+            &self.key,
+            &Context::storage_key(*id),
+        )?;
+
+        Ok(WrappedStorageKey {
+            master_key_id: self.id,
+            wrapped_key,
+        })
+    }
+
+    /// Unwraps the storage key of a file that has been wrapped with this
+    /// master key
+    ///
+    /// # Arguments
+    ///
+    /// * `wrapped_key` - Encoded, encrypted storage key
+    /// * `id` - File id
+    /// * `cipher` - Cipher the file has been encrypted with
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Vec<u8>>`] on success, containing the storage key
+    /// * [`Err<Error>`] on error
+    pub fn unwrap(&self, wrapped_key: &[u8], id: &Uuid, cipher: CipherId) -> Result<Vec<u8>> {
+        cipher.decrypt(wrapped_key.to_vec(), &self.key, &Context::storage_key(*id))
+    }
+}
+
+/// Parses master keys from given `text`, see [`util::parse_secrets`]. Every
+/// master key must be 32 bytes long.
+///
+/// # Arguments
+///
+/// * `text` - Text to parse
+///
+/// # Returns
+///
+/// * [`Ok<Vec<MasterKey>>`] on success
+/// * [`Err<Error>`] if `text` is invalid
+pub fn parse(text: &str) -> Result<Vec<MasterKey>> {
+    let secrets = util::parse_secrets(text).map_err(|position| {
+        Error::InvalidMasterKeyConfiguration(format!("Invalid key at position {position}"))
+    })?;
+
+    secrets
+        .into_iter()
+        .map(|(id, key)| {
+            if key.len() != KEY_LENGTH {
+                return Err(Error::InvalidMasterKeyConfiguration(format!(
+                    "Key {id} is not {KEY_LENGTH} bytes long"
+                )));
+            }
+
+            Ok(MasterKey { id, key })
+        })
+        .collect()
+}
+
+/// Returns the current master key, which storage keys of new files are
+/// wrapped with
+///
+/// # Returns
+///
+/// * [`Some<&MasterKey>`] containing the master key with the highest id
+/// * [`None`] if no master keys are configured
+pub fn current() -> Option<&'static MasterKey> {
+    CONFIGURATION
+        .master_keys
+        .iter()
+        .max_by_key(|master_key| master_key.id)
+}
+
+/// Returns the master key with given `id`
+///
+/// # Returns
+///
+/// * [`Ok<&MasterKey>`] if master key is configured
+/// * [`Err<Error>`] otherwise
+pub fn get(id: i16) -> Result<&'static MasterKey> {
+    CONFIGURATION
+        .master_keys
+        .iter()
+        .find(|master_key| master_key.id == id)
+        .ok_or(Error::MasterKeyUnavailable(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_keys_parsed() {
+        let text = "1:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+        let master_keys = parse(text).unwrap();
+        assert_eq!(1, master_keys[0].id);
+        assert_eq!(vec![1; KEY_LENGTH], master_keys[0].key);
+
+        assert!(parse("1:c2VjcmV0").is_err());
+        assert!(parse(&format!("{text},{text}")).is_err());
+    }
+
+    #[test]
+    fn storage_key_unwrapped_by_master_key_only() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::default();
+        let master_key = MasterKey {
+            id: 1,
+            key: vec![1; KEY_LENGTH],
+        };
+        let other_master_key = MasterKey {
+            id: 2,
+            key: vec![2; KEY_LENGTH],
+        };
+
+        let wrapped = master_key.wrap(&[7; 32], &id, cipher).unwrap();
+        assert_eq!(1, wrapped.master_key_id);
+
+        let unwrapped = master_key.unwrap(&wrapped.wrapped_key, &id, cipher);
+        assert_eq!(vec![7; 32], unwrapped.unwrap());
+
+        assert!(other_master_key
+            .unwrap(&wrapped.wrapped_key, &id, cipher)
+            .is_err());
+        assert!(master_key
+            .unwrap(&wrapped.wrapped_key, &Uuid::new_v4(), cipher)
+            .is_err());
+    }
+}
@@ -1,10 +1,16 @@
 //! Encryption module.
 //!
 //! This module provides encryption functionalities. It includes submodules
-//! for encryption definitions. Currently this contains the XChaCha20Poly1305
-//! encryption scheme.
+//! for encryption definitions, the container format of encrypted data and
+//! master keys. Data can be encrypted with XChaCha20Poly1305 or
+//! AES-256-GCM-SIV, see [`CipherId`].
+mod aead;
+mod aes256gcmsiv;
+mod cipher;
+mod container;
 pub(crate) mod definitions;
+pub mod master_key;
 mod xchacha20poly1305;
 
+pub use cipher::CipherId;
 pub use definitions::*;
-pub use xchacha20poly1305::XChaCha20Poly1305Data as Data;
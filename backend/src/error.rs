@@ -14,6 +14,12 @@ pub enum Error {
     LoadingFileFailed(std::io::Error),
     DeletingFileFailed(std::io::Error),
     ReadingDirectoryFailed(std::io::Error),
+    ReadingDataFailed(std::io::Error),
+    InvalidStorageConfiguration(String),
+    InvalidPepperConfiguration(String),
+    InvalidMasterKeyConfiguration(String),
+    MasterKeyUnavailable(i16),
+    DownloadReservationLost,
     EncryptionFailed,
     DecryptionFailed,
     KeyInvalid,
@@ -36,6 +42,18 @@ impl fmt::Debug for Error {
             Self::LoadingFileFailed(inner) => write!(f, "Loading file failed: {inner}"),
             Self::DeletingFileFailed(inner) => write!(f, "Removing file failed: {inner}"),
             Self::ReadingDirectoryFailed(inner) => write!(f, "Reading directory failed: {inner}"),
+            Self::ReadingDataFailed(inner) => write!(f, "Reading data failed: {inner}"),
+            Self::InvalidStorageConfiguration(inner) => {
+                write!(f, "Invalid storage configuration: {inner}")
+            }
+            Self::InvalidPepperConfiguration(inner) => {
+                write!(f, "Invalid pepper configuration: {inner}")
+            }
+            Self::InvalidMasterKeyConfiguration(inner) => {
+                write!(f, "Invalid master key configuration: {inner}")
+            }
+            Self::MasterKeyUnavailable(id) => write!(f, "Master key {id} unavailable"),
+            Self::DownloadReservationLost => write!(f, "Download reservation lost"),
             Self::EncryptionFailed => write!(f, "Encryption failed"),
             Self::DecryptionFailed => write!(f, "Decryption failed"),
             Self::KeyInvalid => write!(f, "Key invalid"),
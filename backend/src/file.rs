@@ -1,15 +1,24 @@
-//! Module containing functions for saving / reading encrypted data on disk
+//! Module containing functions for saving / reading encrypted data
+//!
+//! Data is stored in the [`storage::StorageBackend`] that is configured. If
+//! master keys are configured, data is additionally encrypted with a storage
+//! key before it's stored, see [`master_key`].
 
 use super::error::{Error, Result};
-use crate::configuration::CONFIGURATION;
+use crate::encryption::master_key::{self, MasterKey, WrappedStorageKey};
+use crate::encryption::{CipherId, Context};
+use crate::hash::{Hash, KeyDerivation};
+use crate::storage::{self, STORAGE};
+use crate::util;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use std::io;
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::{
-    fs::{self, OpenOptions},
-    io::{Read, Write},
-};
+use std::io::{self, Cursor};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
 use uuid::Uuid;
 
 /// File metadata that will be stored serialized and encrypted in the database
@@ -21,107 +30,308 @@ pub struct Metadata {
     pub mime_type: String,
 }
 
-/// Stores new file on disk
+impl Metadata {
+    /// Decrypts metadata that has been stored in encrypted form
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_metadata` - Encoded, encrypted metadata
+    /// * `key` - Decryption key of the file
+    /// * `id` - File id
+    /// * `cipher` - Cipher the file has been encrypted with
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Metadata>`] on success
+    /// * [`Err<Error>`] on error
+    pub fn decrypt(
+        encrypted_metadata: Vec<u8>,
+        key: &[u8],
+        id: &Uuid,
+        cipher: CipherId,
+    ) -> Result<Self> {
+        cipher
+            .decrypt(encrypted_metadata, key, &Context::metadata(*id))
+            .and_then(|data| String::from_utf8(data).map_err(|_| Error::DecryptionFailed))
+            .and_then(|json| serde_json::from_str(&json).map_err(Error::JsonSerializationFailed))
+    }
+}
+
+/// Result of storing the content of a file, see [`store_data`]
+pub struct StoredData {
+    /// Wrapped storage key, if content has been encrypted with one
+    pub storage_key: Option<WrappedStorageKey>,
+    /// Number of bytes that have been written to the storage backend
+    pub size: u64,
+}
+
+/// Encryption key of a file that is protected by a passphrase
+pub struct WrappedKey {
+    /// Key that is handed out instead of the encryption key. Along with the
+    /// passphrase, it's required to unwrap the encryption key.
+    pub link_key: Vec<u8>,
+    /// Salt of the key derived from the passphrase
+    pub salt: Vec<u8>,
+    /// Encoded parameters the key has been derived from the passphrase with
+    pub params: String,
+    /// Encoded, encrypted encryption key
+    pub wrapped_key: Vec<u8>,
+}
+
+impl WrappedKey {
+    /// Wraps encryption `key` of a file with a key derived from `passphrase`
+    /// and a new, random link key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Encryption key of the file
+    /// * `passphrase` - Passphrase to protect the file with
+    /// * `id` - File id
+    /// * `cipher` - Cipher the file is encrypted with
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<WrappedKey>`] on success
+    /// * [`Err<Error>`] on error
+    pub async fn wrap(key: &[u8], passphrase: &str, id: &Uuid, cipher: CipherId) -> Result<Self> {
+        Self::wrap_with_params(key, passphrase, Hash::key_params()?, id, cipher).await
+    }
+
+    /// Wraps encryption `key` like [`WrappedKey::wrap`], deriving the
+    /// wrapping key with given encoded `params`
+    async fn wrap_with_params(
+        key: &[u8],
+        passphrase: &str,
+        params: String,
+        id: &Uuid,
+        cipher: CipherId,
+    ) -> Result<Self> {
+        let link_key = util::generate_token();
+        let salt = util::generate_token();
+
+        let wrapping_key = derive_key(passphrase, &salt, &link_key, Some(&params)).await?;
+        let wrapped_key =
+            cipher.encrypt_with_key(key.iter().copied(), &wrapping_key, &Context::key(*id))?;
+
+        Ok(Self {
+            link_key,
+            salt,
+            params,
+            wrapped_key,
+        })
+    }
+}
+
+/// Derives a key from given `passphrase` on a blocking thread, so that the
+/// runtime isn't blocked, see [`KeyDerivation::derive_key`]
+async fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    secret: &[u8],
+    params: Option<&str>,
+) -> Result<Vec<u8>> {
+    let passphrase = passphrase.as_bytes().to_vec();
+    let salt = salt.to_vec();
+    let secret = secret.to_vec();
+    let params = params.map(str::to_string);
+
+    tokio::task::spawn_blocking(move || {
+        Hash::derive_key(&passphrase, &salt, &secret, params.as_deref())
+    })
+    .await
+    .map_err(|error| Error::HashingFailure(error.to_string()))?
+}
+
+/// Gets the encryption key of given `file`
+///
+/// If the file is protected by a passphrase, its wrapped encryption key is
+/// unwrapped with `passphrase` and the given (validated) `key`. Otherwise
+/// `key` already is the encryption key.
 ///
 /// # Arguments
 ///
-/// * `id` - File id (to use as file name)
-/// * `content` - Content to store
+/// * `file` - File to get encryption key of
+/// * `key` - Validated key that has been handed out on upload
+/// * `passphrase` - Passphrase of the file, if any
+/// * `cipher` - Cipher the file has been encrypted with
 ///
 /// # Returns
 ///
-/// * [`Ok<PathBuf>`] on success with file path
+/// * [`Ok<Vec<u8>>`] on success, containing the encryption key
+/// * [`Err<Error>`] on error, e.g. if passphrase is missing or wrong
+pub async fn get_encryption_key(
+    file: &entity::file::Model,
+    key: Vec<u8>,
+    passphrase: Option<&str>,
+    cipher: CipherId,
+) -> Result<Vec<u8>> {
+    let (Some(salt), Some(wrapped_key)) = (&file.key_salt, &file.wrapped_key) else {
+        return Ok(key);
+    };
+
+    /* Keys wrapped before parameters have been stored used default ones */
+    let passphrase = passphrase.ok_or(Error::KeyInvalid)?;
+    let wrapping_key = derive_key(passphrase, salt, &key, file.key_params.as_deref()).await?;
+    let id = Uuid::from_slice(&file.id).map_err(|_| Error::KeyInvalid)?;
+
+    cipher
+        .decrypt(wrapped_key.clone(), &wrapping_key, &Context::key(id))
+        .map_err(|_| Error::KeyInvalid)
+}
+
+/// Stores new file
+///
+/// `content` is stored chunk by chunk while it is being polled. If `content`
+/// yields an error, the partially stored file is deleted and the error is
+/// returned. If master keys are configured, `content` is encrypted with a new
+/// storage key, which is wrapped with the current master key.
+///
+/// # Arguments
+///
+/// * `id` - File id
+/// * `content` - Stream of content to store
+/// * `cipher` - Cipher the file is encrypted with
+///
+/// # Returns
+///
+/// * [`Ok<StoredData>`] on success, containing the wrapped storage key, if
+///   master keys are configured, and the stored size
 /// * [`Err<Error>`] on error
-pub fn store_data(id: &Uuid, content: Vec<u8>) -> Result<PathBuf> {
-    let mut file_path = CONFIGURATION.file_path.clone();
-    file_path.push(id.to_string());
-
-    let mut file = OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(&file_path)
-        .map_err(Error::SavingFileFailed)?;
-
-    if let Err(error) = file.write_all(&content) {
-        delete(id)?;
-        return Err(Error::SavingFileFailed(error));
-    }
+pub async fn store_data<S: Stream<Item = Result<Vec<u8>>> + Send + 'static>(
+    id: &Uuid,
+    content: S,
+    cipher: CipherId,
+) -> Result<StoredData> {
+    let (data, storage_key) = match master_key::current() {
+        None => (content.boxed(), None),
+        Some(master_key) => {
+            let (sealed, wrapped_key) = seal(id, content, master_key, cipher)?;
+            (sealed, Some(wrapped_key))
+        }
+    };
 
-    drop(content);
+    let size = Arc::new(AtomicU64::new(0));
+    let counted_size = Arc::clone(&size);
 
-    if let Err(error) = file.sync_all() {
-        delete(id)?;
-        return Err(Error::SavingFileFailed(error));
-    }
+    let data = data.inspect_ok(move |chunk| {
+        counted_size.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    });
+
+    STORAGE.store(id, data.boxed()).await?;
 
-    Ok(file_path)
+    Ok(StoredData {
+        storage_key,
+        size: size.load(Ordering::Relaxed),
+    })
 }
 
 /// Retrieves the Ids of all stored files.
 ///
-/// This function reads the directory and collects the UUIDs of all files stored.
-///
 /// # Returns
 ///
 /// * [`Ok<Vec<Uuid>>`] - A vector containing the UUIDs of all stored files.
 /// * [`Err<Error>`] on error
-pub fn get_stored_file_ids() -> Result<Vec<Uuid>> {
-    let mut file_ids = vec![];
-
-    let read_dir =
-        fs::read_dir(CONFIGURATION.file_path.clone()).map_err(Error::ReadingDirectoryFailed)?;
-
-    for dir_entry in read_dir {
-        let file_name = dir_entry
-            .map_err(Error::ReadingDirectoryFailed)?
-            .file_name();
-
-        let file_name = file_name
-            .to_str()
-            .ok_or(Error::ReadingDirectoryFailed(io::Error::new(
-                io::ErrorKind::Other,
-                "Could not get file name",
-            )))?;
-
-        let file_id = Uuid::from_str(file_name).map_err(|_| {
-            Error::ReadingDirectoryFailed(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("File name not a Uuid: {file_name}"),
-            ))
-        })?;
-
-        file_ids.push(file_id);
-    }
+pub async fn get_stored_file_ids() -> Result<Vec<Uuid>> {
+    STORAGE.stored_ids().await
+}
+
+/// Opens stored data for reading
+///
+/// If the data has been encrypted with a storage key, it's decrypted while
+/// it's being read.
+///
+/// # Arguments
+///
+/// * `file` - File to load data of
+///
+/// # Returns
+///
+/// * [`Ok<storage::DataReader>`] on success, containing reader of file content
+/// * [`Err<Error>`] on error
+pub async fn load_data(file: &entity::file::Model) -> Result<storage::DataReader> {
+    let id = Uuid::from_slice(&file.id).map_err(|_| Error::KeyInvalid)?;
+    let data = STORAGE.load(&id).await?;
+
+    let (Some(master_key_id), Some(wrapped_key)) = (file.master_key_id, &file.wrapped_storage_key)
+    else {
+        return Ok(data);
+    };
 
-    Ok(file_ids)
+    let master_key = master_key::get(master_key_id)?;
+    let cipher = CipherId::try_from(file.cipher)?;
+
+    open(&id, data, master_key, wrapped_key, cipher)
 }
 
-/// Load data from disk
+/// Encrypts `content` of a file with a new storage key, which is wrapped with
+/// given `master_key`
 ///
 /// # Arguments
 ///
 /// * `id` - File id
+/// * `content` - Stream of content to encrypt
+/// * `master_key` - Master key to wrap storage key with
+/// * `cipher` - Cipher to encrypt with
 ///
 /// # Returns
 ///
-/// * [`Ok<Vec<u8>>`] on success, containing file content
+/// * [`Ok<(BoxStream, WrappedStorageKey)>`] on success, containing stream of
+///   encrypted content and wrapped storage key
 /// * [`Err<Error>`] on error
-pub fn load_data(id: &Uuid) -> Result<Vec<u8>> {
-    let mut file_path = CONFIGURATION.file_path.clone();
-    file_path.push(id.to_string());
+fn seal<S: Stream<Item = Result<Vec<u8>>> + Send + 'static>(
+    id: &Uuid,
+    content: S,
+    master_key: &MasterKey,
+    cipher: CipherId,
+) -> Result<(BoxStream<'static, Result<Vec<u8>>>, WrappedStorageKey)> {
+    let (sealed, storage_key) = cipher.encrypt_stream(into_reader(content), Context::storage(*id));
+    let wrapped_key = master_key.wrap(&storage_key, id, cipher)?;
 
-    let mut content = vec![];
+    Ok((sealed, wrapped_key))
+}
 
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open(&file_path)
-        .map_err(Error::LoadingFileFailed)?;
+/// Decrypts stored `data` of a file, that has been encrypted with a storage
+/// key, while it's being read
+///
+/// # Arguments
+///
+/// * `id` - File id
+/// * `data` - Reader of stored data
+/// * `master_key` - Master key the storage key is wrapped with
+/// * `wrapped_key` - Wrapped storage key
+/// * `cipher` - Cipher the data has been encrypted with
+///
+/// # Returns
+///
+/// * [`Ok<storage::DataReader>`] on success, containing reader of file content
+/// * [`Err<Error>`] on error
+fn open(
+    id: &Uuid,
+    data: storage::DataReader,
+    master_key: &MasterKey,
+    wrapped_key: &[u8],
+    cipher: CipherId,
+) -> Result<storage::DataReader> {
+    let storage_key = master_key.unwrap(wrapped_key, id, cipher)?;
+    let opened = cipher.decrypt_stream(data, &storage_key, Context::storage(*id))?;
 
-    if let Err(error) = file.read_to_end(&mut content) {
-        return Err(Error::LoadingFileFailed(error));
-    };
+    Ok(Box::new(into_reader(opened)))
+}
 
-    Ok(content)
+/// Converts given stream of `content` into a reader. Errors of reading data
+/// keep their kind, e.g. to report that the body of a request is too large.
+fn into_reader<S: Stream<Item = Result<Vec<u8>>> + Send + 'static>(
+    content: S,
+) -> impl AsyncRead + Unpin + Send + 'static {
+    StreamReader::new(
+        content
+            .map_ok(Cursor::new)
+            .map_err(|error| match error {
+                Error::ReadingDataFailed(error) => error,
+                error => io::Error::other(format!("{error:?}")),
+            })
+            .boxed(),
+    )
 }
 
 /// Ensure file is deleted
@@ -134,23 +344,133 @@ pub fn load_data(id: &Uuid) -> Result<Vec<u8>> {
 ///
 /// * [`Ok<()>`] ensuring file doesn't exist (anymore)  
 /// * [`Err<Error>`] on error
-pub fn delete(id: &Uuid) -> Result<()> {
-    let mut file_path = CONFIGURATION.file_path.clone();
-    file_path.push(id.to_string());
+pub async fn delete(id: &Uuid) -> Result<()> {
+    STORAGE.delete(id).await
+}
 
-    if !(fs::exists(&file_path).map_err(Error::DeletingFileFailed)?) {
-        return Ok(());
+/// Removes stale, never completed data of files
+///
+/// # Arguments
+///
+/// * `max_age` - Age of incomplete data after which it's considered stale
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+pub async fn purge_stale_staged_data(max_age: Duration) -> Result<()> {
+    STORAGE.purge_stale_staged(max_age).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    /// Cheap key derivation parameters, as tests don't need to be costly
+    const KEY_PARAMS: &str = "$argon2id$v=19$m=32,t=1,p=1";
+
+    fn create_file(id: &Uuid, wrapped: Option<&WrappedKey>) -> entity::file::Model {
+        entity::file::Model {
+            key_salt: wrapped.map(|wrapped| wrapped.salt.clone()),
+            wrapped_key: wrapped.map(|wrapped| wrapped.wrapped_key.clone()),
+            key_params: wrapped.map(|wrapped| wrapped.params.clone()),
+            ..test_util::file_model(id)
+        }
     }
 
-    if !fs::metadata(&file_path)
-        .map_err(Error::DeletingFileFailed)?
-        .is_file()
-    {
-        return Err(Error::DeletingFileFailed(io::Error::new(
-            io::ErrorKind::IsADirectory,
-            "Directory given",
-        )));
+    #[tokio::test]
+    async fn sealed_data_opened_with_master_key() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::default();
+        let master_key = MasterKey {
+            id: 1,
+            key: vec![1; 32],
+        };
+
+        let content = stream::iter(vec![Ok(vec![1, 2]), Ok(vec![3])]);
+        let (sealed, wrapped) = seal(&id, content, &master_key, cipher).unwrap();
+        let sealed = sealed.try_concat().await.unwrap();
+        assert!(!sealed.windows(3).any(|window| window == [1, 2, 3]));
+
+        let mut opened = vec![];
+        open(
+            &id,
+            Box::new(Cursor::new(sealed)),
+            &master_key,
+            &wrapped.wrapped_key,
+            cipher,
+        )
+        .unwrap()
+        .read_to_end(&mut opened)
+        .await
+        .unwrap();
+        assert_eq!(vec![1, 2, 3], opened);
     }
 
-    fs::remove_file(&file_path).map_err(Error::DeletingFileFailed)
+    #[tokio::test]
+    async fn unprotected_key_returned() {
+        let id = Uuid::new_v4();
+        let file = create_file(&id, None);
+
+        let key = get_encryption_key(&file, vec![1, 2, 3], None, CipherId::default()).await;
+        assert_eq!(vec![1, 2, 3], key.unwrap());
+    }
+
+    #[tokio::test]
+    async fn wrapped_key_requires_link_key_and_passphrase() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::Aes256GcmSiv;
+        let key = [7; 32];
+
+        let wrapped = WrappedKey::wrap_with_params(&key, "secret", KEY_PARAMS.into(), &id, cipher)
+            .await
+            .unwrap();
+        let file = create_file(&id, Some(&wrapped));
+        let link_key = wrapped.link_key.clone();
+
+        let unwrapped = get_encryption_key(&file, link_key.clone(), Some("secret"), cipher).await;
+        assert_eq!(key.to_vec(), unwrapped.unwrap());
+
+        assert!(get_encryption_key(&file, link_key.clone(), None, cipher)
+            .await
+            .is_err());
+        assert!(get_encryption_key(&file, link_key, Some("wrong"), cipher)
+            .await
+            .is_err());
+        assert!(
+            get_encryption_key(&file, key.to_vec(), Some("secret"), cipher)
+                .await
+                .is_err()
+        );
+        assert!(get_encryption_key(
+            &create_file(&Uuid::new_v4(), Some(&wrapped)),
+            wrapped.link_key.clone(),
+            Some("secret"),
+            cipher
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn wrapped_key_derived_with_stored_params() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::default();
+        let key = [7; 32];
+
+        let wrapped = WrappedKey::wrap_with_params(&key, "secret", KEY_PARAMS.into(), &id, cipher)
+            .await
+            .unwrap();
+        let mut file = create_file(&id, Some(&wrapped));
+        let link_key = wrapped.link_key.clone();
+
+        /* Keys wrapped without stored parameters are derived with default ones */
+        file.key_params = None;
+        assert!(get_encryption_key(&file, link_key, Some("secret"), cipher)
+            .await
+            .is_err());
+    }
 }
@@ -1,28 +1,270 @@
-use super::definitions::Hashing;
+use super::definitions::{Hashing, KeyDerivation};
+use super::pepper::Pepper;
+use crate::configuration::CONFIGURATION;
 use crate::error::{Error, Result};
 use argon2::password_hash::{
-    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    rand_core::OsRng, ParamsString, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
 };
+use argon2::{Algorithm, Params, Version};
+use std::time::{Duration, Instant};
+
+/// Length of derived keys in bytes
+const DERIVED_KEY_LENGTH: usize = 32;
 
 /// A struct representing the Argon2 hashing algorithm.
+///
+/// New hashes are created with the Argon2 parameters of [`CONFIGURATION`].
+/// Hashes are always verified with the parameters encoded in them, so hashes
+/// created with other parameters stay valid.
 pub struct Argon2 {}
 
-impl Hashing for Argon2 {
-    fn hash(data: &[u8]) -> Result<String> {
+impl Argon2 {
+    /// Measures the time it takes to hash data with the configured
+    /// parameters
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Duration>`] on success, containing the time hashing took
+    /// * [`Err<Error>`] on error
+    pub fn benchmark() -> Result<Duration> {
+        let start = Instant::now();
+        Self::hash_with_params(&[0; 32], CONFIGURATION.argon2_params.clone(), None)?;
+
+        Ok(start.elapsed())
+    }
+
+    /// Hashes given `data` with Argon2id and given `params`, mixing in given
+    /// `pepper` as secret
+    fn hash_with_params(data: &[u8], params: Params, pepper: Option<&Pepper>) -> Result<String> {
         let salt = SaltString::generate(&mut OsRng);
 
-        Ok(argon2::Argon2::default()
+        Self::create(params, pepper)?
             .hash_password(data, &salt)
-            .map_err(|error| Error::HashingFailure(error.to_string()))?
-            .to_string())
+            .map(|hash| hash.to_string())
+            .map_err(|error| Error::HashingFailure(error.to_string()))
+    }
+
+    /// Creates Argon2id instance with given `params` and `pepper` as secret
+    fn create(params: Params, pepper: Option<&Pepper>) -> Result<argon2::Argon2<'_>> {
+        match pepper {
+            None => Ok(argon2::Argon2::new(
+                Algorithm::Argon2id,
+                Version::V0x13,
+                params,
+            )),
+            Some(pepper) => argon2::Argon2::new_with_secret(
+                &pepper.secret,
+                Algorithm::Argon2id,
+                Version::V0x13,
+                params,
+            )
+            .map_err(|error| Error::HashingFailure(error.to_string())),
+        }
+    }
+
+    /// Checks whether given `hash` is weaker than a hash created with Argon2id
+    /// and given `params`
+    fn is_weaker(hash: &str, params: &Params) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hash) else {
+            return true;
+        };
+
+        parsed_hash.algorithm != argon2::ARGON2ID_IDENT
+            || parsed_hash.version != Some(Version::V0x13.into())
+            || Params::try_from(&parsed_hash).map_or(true, |hash_params| {
+                hash_params.m_cost() < params.m_cost()
+                    || hash_params.t_cost() < params.t_cost()
+                    || hash_params.p_cost() < params.p_cost()
+            })
+    }
+
+    /// Encodes given `params` of Argon2id as PHC string without salt and
+    /// hash, e.g. `$argon2id$v=19$m=19456,t=2,p=1`
+    fn encode_params(params: &Params) -> Result<String> {
+        let params = ParamsString::try_from(params)
+            .map_err(|error| Error::HashingFailure(error.to_string()))?;
+
+        Ok(PasswordHash {
+            algorithm: argon2::ARGON2ID_IDENT,
+            version: Some(Version::V0x13.into()),
+            params,
+            salt: None,
+            hash: None,
+        }
+        .to_string())
+    }
+
+    /// Decodes Argon2id parameters that have been encoded by
+    /// [`Argon2::encode_params`]
+    fn decode_params(params: &str) -> Result<Params> {
+        let parsed =
+            PasswordHash::new(params).map_err(|error| Error::HashingFailure(error.to_string()))?;
+
+        if parsed.algorithm != argon2::ARGON2ID_IDENT
+            || parsed.version != Some(Version::V0x13.into())
+        {
+            return Err(Error::HashingFailure(format!(
+                "Unsupported key derivation: {params}"
+            )));
+        }
+
+        Params::try_from(&parsed).map_err(|error| Error::HashingFailure(error.to_string()))
+    }
+
+    /// Checks whether given `hash` has been created with Argon2id and
+    /// parameters that don't exceed given `params`
+    fn is_within(hash: &str, params: &Params) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hash) else {
+            return false;
+        };
+
+        parsed_hash.algorithm == argon2::ARGON2ID_IDENT
+            && parsed_hash.hash.is_some()
+            && Params::try_from(&parsed_hash).is_ok_and(|hash_params| {
+                hash_params.m_cost() <= params.m_cost()
+                    && hash_params.t_cost() <= params.t_cost()
+                    && hash_params.p_cost() <= params.p_cost()
+            })
     }
+}
 
-    fn verify(data: &[u8], hash: &str) -> Result<bool> {
+impl Hashing for Argon2 {
+    fn hash(data: &[u8], pepper: Option<&Pepper>) -> Result<String> {
+        Self::hash_with_params(data, CONFIGURATION.argon2_params.clone(), pepper)
+    }
+
+    fn verify(data: &[u8], hash: &str, pepper: Option<&Pepper>) -> Result<bool> {
         let parsed_hash = PasswordHash::new(hash)
             .map_err(|error| Error::HashVerificationFailure(error.to_string()))?;
 
-        Ok(argon2::Argon2::default()
+        /* Parameters are taken from the hash itself */
+        Ok(Self::create(Params::default(), pepper)?
             .verify_password(data, &parsed_hash)
             .is_ok())
     }
+
+    fn needs_rehash(hash: &str) -> bool {
+        Self::is_weaker(hash, &CONFIGURATION.argon2_params)
+    }
+
+    fn is_acceptable(hash: &str) -> bool {
+        Self::is_within(hash, &CONFIGURATION.argon2_params)
+    }
+}
+
+impl KeyDerivation for Argon2 {
+    fn key_params() -> Result<String> {
+        Self::encode_params(&CONFIGURATION.argon2_params)
+    }
+
+    fn derive_key(
+        passphrase: &[u8],
+        salt: &[u8],
+        secret: &[u8],
+        params: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let params = match params {
+            None => Params::default(),
+            Some(params) => Self::decode_params(params)?,
+        };
+
+        let mut key = vec![0u8; DERIVED_KEY_LENGTH];
+
+        argon2::Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, params)
+            .and_then(|argon2| argon2.hash_password_into(passphrase, salt, &mut key))
+            .map_err(|error| Error::HashingFailure(error.to_string()))?;
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_hash_accepted() {
+        let hash = Argon2::hash_with_params(b"token", Params::default(), None).unwrap();
+
+        assert!(Argon2::is_within(&hash, &Params::default()));
+        assert!(Argon2::verify(b"token", &hash, None).unwrap());
+    }
+
+    #[test]
+    fn costly_or_invalid_hash_not_accepted() {
+        let params = Params::default();
+
+        assert!(!Argon2::is_within("xxxYYY", &params));
+        assert!(!Argon2::is_within(
+            "$argon2i$v=19$m=12,t=3,p=1$dzc0OGd1OWZveHMwMDAwMA$c76OJ4RDh1TlW1tdcbimWA",
+            &params
+        ));
+
+        /* Every parameter is capped at the configured one */
+        for (m_cost, t_cost, p_cost) in [
+            (params.m_cost() + 1, params.t_cost(), params.p_cost()),
+            (params.m_cost(), params.t_cost() + 1, params.p_cost()),
+            (params.m_cost(), params.t_cost(), params.p_cost() + 1),
+        ] {
+            let costly_params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+            let hash = Argon2::hash_with_params(b"token", costly_params, None).unwrap();
+
+            assert!(!Argon2::is_within(&hash, &params));
+        }
+    }
+
+    #[test]
+    fn weaker_hash_verified_and_detected() {
+        let weak_params = Params::new(Params::MIN_M_COST * 4, 1, 1, None).unwrap();
+        let hash = Argon2::hash_with_params(b"key", weak_params.clone(), None).unwrap();
+
+        assert!(Argon2::verify(b"key", &hash, None).unwrap());
+        assert!(!Argon2::verify(b"other key", &hash, None).unwrap());
+
+        assert!(Argon2::is_weaker(&hash, &Params::default()));
+        assert!(!Argon2::is_weaker(&hash, &weak_params));
+        assert!(Argon2::is_weaker(
+            "$argon2i$v=19$m=12,t=3,p=1$dzc0OGd1OWZveHMwMDAwMA$c76OJ4RDh1TlW1tdcbimWA",
+            &weak_params
+        ));
+    }
+
+    #[test]
+    fn peppered_hash_verified_with_pepper_only() {
+        let pepper = Pepper {
+            id: 1,
+            secret: b"pepper".to_vec(),
+        };
+        let other_pepper = Pepper {
+            id: 2,
+            secret: b"other pepper".to_vec(),
+        };
+
+        let hash = Argon2::hash_with_params(b"key", Params::default(), Some(&pepper)).unwrap();
+
+        assert!(Argon2::verify(b"key", &hash, Some(&pepper)).unwrap());
+        assert!(!Argon2::verify(b"key", &hash, Some(&other_pepper)).unwrap());
+        assert!(!Argon2::verify(b"key", &hash, None).unwrap());
+    }
+
+    #[test]
+    fn key_derived_with_encoded_params() {
+        let params = Params::new(Params::MIN_M_COST * 4, 1, 1, None).unwrap();
+        let encoded = Argon2::encode_params(&params).unwrap();
+
+        assert_eq!("$argon2id$v=19$m=32,t=1,p=1", encoded);
+
+        let key = Argon2::derive_key(b"passphrase", b"saltsalt", b"secret", Some(&encoded));
+        let default_key = Argon2::derive_key(b"passphrase", b"saltsalt", b"secret", None);
+
+        assert_eq!(DERIVED_KEY_LENGTH, key.as_ref().unwrap().len());
+        assert_ne!(key.unwrap(), default_key.unwrap());
+        assert!(Argon2::derive_key(
+            b"passphrase",
+            b"saltsalt",
+            b"secret",
+            Some("$argon2i$v=19$m=32,t=1,p=1")
+        )
+        .is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use super::pepper::Pepper;
 use crate::error::Result;
 
 /// Provides functions to hash data or to verify hashes
@@ -7,12 +8,13 @@ pub trait Hashing {
     /// # Arguments
     ///
     /// * `data` - Data to hash
+    /// * `pepper` - Pepper to mix into the hash, if any
     ///
     /// # Returns
     ///
     /// * [`Ok<String>`] on success, containing the hash
     /// * [`Err<Error>`] on error
-    fn hash(data: &[u8]) -> Result<String>;
+    fn hash(data: &[u8], pepper: Option<&Pepper>) -> Result<String>;
 
     /// Verifies given `data` against `hash`
     ///
@@ -20,11 +22,72 @@ pub trait Hashing {
     ///
     /// * `data` - Data to verify hash against
     /// * `hash` - Hash to verify
+    /// * `pepper` - Pepper that has been mixed into the hash, if any
     ///
     /// # Returns
     ///
     /// * [`Ok<true>`] on `data` matching `hash`
     /// * [`Ok<false>`] on `data` **not** matching `hash`
     /// * [`Err<Error>`] on error
-    fn verify(data: &[u8], hash: &str) -> Result<bool>;
+    fn verify(data: &[u8], hash: &str, pepper: Option<&Pepper>) -> Result<bool>;
+
+    /// Checks whether given `hash` has been created with weaker parameters
+    /// than new hashes are, so that it should be replaced
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Hash to check
+    ///
+    /// # Returns
+    ///
+    /// * `true` if `hash` should be replaced
+    /// * `false` otherwise
+    fn needs_rehash(hash: &str) -> bool;
+
+    /// Checks whether given `hash`, e.g. supplied by a client, can be used
+    /// to verify data against without costing more than hashing new data
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Hash to check
+    ///
+    /// # Returns
+    ///
+    /// * `true` if `hash` is acceptable
+    /// * `false` otherwise
+    fn is_acceptable(hash: &str) -> bool;
+}
+
+/// Provides functions to derive encryption keys from passphrases
+pub trait KeyDerivation {
+    /// Returns the encoded parameters that new keys are derived with. They
+    /// must be stored along with derived keys, so that keys can be derived
+    /// again after parameters have been changed.
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<String>`] on success, containing the encoded parameters
+    /// * [`Err<Error>`] on error
+    fn key_params() -> Result<String>;
+
+    /// Derives a key from given `passphrase`
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - Passphrase to derive key from
+    /// * `salt` - Salt to use
+    /// * `secret` - Additional secret that the key depends on
+    /// * `params` - Encoded parameters, see [`KeyDerivation::key_params`].
+    ///   Default parameters are used if [`None`].
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Vec<u8>>`] on success, containing the derived key
+    /// * [`Err<Error>`] on error
+    fn derive_key(
+        passphrase: &[u8],
+        salt: &[u8],
+        secret: &[u8],
+        params: Option<&str>,
+    ) -> Result<Vec<u8>>;
 }
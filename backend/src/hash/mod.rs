@@ -1,9 +1,10 @@
 //! Hash module.
 //!
 //! This module provides hashing functionalities. It includes submodules
-//! for Argon2 hashing and hash definitions.
+//! for Argon2 hashing, hash definitions and peppers.
 mod argon2;
 mod definitions;
+pub mod pepper;
 
 pub use argon2::Argon2 as Hash;
 pub use definitions::*;
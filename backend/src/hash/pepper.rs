@@ -0,0 +1,93 @@
+use crate::configuration::CONFIGURATION;
+use crate::error::{Error, Result};
+use crate::util;
+
+/// Secret that is mixed into hashes of file encryption keys. Peppers are
+/// never stored in the database, so hashes of a database dump can't be
+/// attacked without them.
+pub struct Pepper {
+    /// Id of the pepper, stored along with every hash it's mixed into
+    pub id: i16,
+    /// Secret of the pepper
+    pub secret: Vec<u8>,
+}
+
+/// Parses peppers from given `text`, see [`util::parse_secrets`]
+///
+/// # Arguments
+///
+/// * `text` - Text to parse
+///
+/// # Returns
+///
+/// * [`Ok<Vec<Pepper>>`] on success
+/// * [`Err<Error>`] if `text` is invalid
+pub fn parse(text: &str) -> Result<Vec<Pepper>> {
+    util::parse_secrets(text)
+        .map(|secrets| {
+            secrets
+                .into_iter()
+                .map(|(id, secret)| Pepper { id, secret })
+                .collect()
+        })
+        .map_err(|position| {
+            Error::InvalidPepperConfiguration(format!("Invalid pepper at position {position}"))
+        })
+}
+
+/// Returns the current pepper, which is mixed into new hashes
+///
+/// # Returns
+///
+/// * [`Some<&Pepper>`] containing the pepper with the highest id
+/// * [`None`] if no peppers are configured
+pub fn current() -> Option<&'static Pepper> {
+    CONFIGURATION.peppers.iter().max_by_key(|pepper| pepper.id)
+}
+
+/// Returns the pepper with given `id`
+///
+/// # Returns
+///
+/// * [`Some<&Pepper>`] if pepper is configured
+/// * [`None`] otherwise
+pub fn get(id: i16) -> Option<&'static Pepper> {
+    CONFIGURATION.peppers.iter().find(|pepper| pepper.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peppers_parsed() {
+        let peppers = parse("1:c2VjcmV0, 2:b3RoZXI=\n\n3:dGhpcmQ=\n").unwrap();
+
+        assert_eq!(
+            vec![1, 2, 3],
+            peppers.iter().map(|pepper| pepper.id).collect::<Vec<_>>()
+        );
+        assert_eq!(b"secret".to_vec(), peppers[0].secret);
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_peppers_not_parsed() {
+        assert!(parse("c2VjcmV0").is_err());
+        assert!(parse("0:c2VjcmV0").is_err());
+        assert!(parse("1:").is_err());
+        assert!(parse("1:@@@").is_err());
+        assert!(parse("1:c2VjcmV0,1:b3RoZXI=").is_err());
+    }
+
+    #[test]
+    fn invalid_pepper_reported_by_position() {
+        let Err(Error::InvalidPepperConfiguration(message)) = parse("1:c2VjcmV0,\n1:c2VjcmV0")
+        else {
+            panic!("Duplicate pepper id not reported");
+        };
+
+        assert_eq!("Invalid pepper at position 2", message);
+        assert!(!message.contains("c2VjcmV0"));
+    }
+}
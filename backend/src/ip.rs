@@ -0,0 +1,165 @@
+//! Module containing functions for anonymizing client IPs
+//!
+//! Depending on configuration, client IPs are stored as they are, as keyed
+//! HMAC or truncated to their network prefix. Anonymized IPs of the same
+//! client are still equal, so the upload limit and attempt counting work the
+//! same way in every mode.
+
+use crate::error::{Error, Result};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Min length of HMAC keys in bytes
+pub const MIN_HMAC_KEY_LENGTH: usize = 32;
+
+/// Number of leading bits of IPv4 addresses that are kept on truncation
+const IPV4_PREFIX_LENGTH: u32 = 24;
+
+/// Number of leading bits of IPv6 addresses that are kept on truncation
+const IPV6_PREFIX_LENGTH: u32 = 48;
+
+/// How client IPs are anonymized before they are stored
+pub enum Anonymization {
+    /// IPs are stored as they are
+    None,
+    /// IPs are replaced by their HMAC with given key
+    Hmac(Vec<u8>),
+    /// IPs are truncated to their network prefix, e.g. `192.168.1.0`
+    Truncate,
+}
+
+/// Gets the ip of the client from given value of the ip header
+///
+/// Proxy servers may append their own ips to the header, so the header
+/// contains a comma-separated list of ips. The ip of the client is taken from
+/// given `position` and validated.
+///
+/// # Arguments
+///
+/// * `header_value` - Value of the ip header
+/// * `position` - Position of the client ip in the list, starting at 0.
+///   Negative positions count from the end, e.g. -1 for the last ip.
+///
+/// # Returns
+///
+/// * [`Ok<IpAddr>`] containing the ip of the client
+/// * [`Err<Error>`] if there's no valid ip at `position`
+pub fn get_client_ip(header_value: &str, position: i32) -> Result<IpAddr> {
+    let ips = header_value.split(',').collect::<Vec<_>>();
+
+    let index = match usize::try_from(position) {
+        Ok(index) => Some(index),
+        Err(_) => ips.len().checked_sub(position.unsigned_abs() as usize),
+    };
+
+    index
+        .and_then(|index| ips.get(index))
+        .and_then(|ip| ip.trim().parse().ok())
+        .ok_or(Error::IpHeaderInvalid)
+}
+
+/// Anonymizes given client `ip`
+///
+/// # Arguments
+///
+/// * `ip` - Ip of the client
+/// * `anonymization` - Kind of anonymization
+///
+/// # Returns
+///
+/// * [`Ok<String>`] containing the (anonymized) ip
+/// * [`Err<Error>`] on error
+pub fn anonymize(ip: IpAddr, anonymization: &Anonymization) -> Result<String> {
+    match anonymization {
+        Anonymization::None => Ok(ip.to_string()),
+        Anonymization::Hmac(key) => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(key).map_err(|_| Error::IpHeaderInvalid)?;
+            mac.update(ip.to_string().as_bytes());
+
+            Ok(BASE64_STANDARD.encode(mac.finalize().into_bytes()))
+        }
+        Anonymization::Truncate => Ok(truncate(ip).to_string()),
+    }
+}
+
+/// Truncates given `ip` to its network prefix
+fn truncate(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX << (Ipv4Addr::BITS - IPV4_PREFIX_LENGTH);
+            IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX << (Ipv6Addr::BITS - IPV6_PREFIX_LENGTH);
+            IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn client_ip_taken_from_position() {
+        let header_value = " 192.168.1.42 , 10.0.0.1,2001:db8::1";
+
+        assert_eq!(ip("192.168.1.42"), get_client_ip(header_value, 0).unwrap());
+        assert_eq!(ip("10.0.0.1"), get_client_ip(header_value, 1).unwrap());
+        assert_eq!(ip("2001:db8::1"), get_client_ip(header_value, -1).unwrap());
+        assert_eq!(ip("192.168.1.42"), get_client_ip(header_value, -3).unwrap());
+        assert!(get_client_ip(header_value, 3).is_err());
+        assert!(get_client_ip(header_value, -4).is_err());
+        assert!(get_client_ip("localhost, 10.0.0.1", 0).is_err());
+    }
+
+    #[test]
+    fn ips_truncated() {
+        let anonymization = Anonymization::Truncate;
+
+        assert_eq!(
+            "192.168.1.0",
+            anonymize(ip("192.168.1.42"), &anonymization).unwrap()
+        );
+        assert_eq!(
+            "2001:db8:1::",
+            anonymize(ip("2001:db8:1:2::42"), &anonymization).unwrap()
+        );
+    }
+
+    #[test]
+    fn ips_hashed_with_key() {
+        let anonymization = Anonymization::Hmac(vec![1; MIN_HMAC_KEY_LENGTH]);
+        let other_anonymization = Anonymization::Hmac(vec![2; MIN_HMAC_KEY_LENGTH]);
+
+        let hashed = anonymize(ip("192.168.1.42"), &anonymization).unwrap();
+
+        assert_ne!("192.168.1.42", hashed);
+        assert_ne!(
+            hashed,
+            anonymize(ip("192.168.1.43"), &anonymization).unwrap()
+        );
+        assert_ne!(
+            hashed,
+            anonymize(ip("192.168.1.42"), &other_anonymization).unwrap()
+        );
+
+        /* The same client is hashed equally, regardless of the proxy chain */
+        for header_value in [
+            "192.168.1.42",
+            "192.168.1.42, 10.0.0.1",
+            "192.168.1.42,10.0.0.2",
+        ] {
+            let client_ip = get_client_ip(header_value, 0).unwrap();
+            assert_eq!(hashed, anonymize(client_ip, &anonymization).unwrap());
+        }
+    }
+}
@@ -2,8 +2,8 @@ use configuration::CONFIGURATION;
 use laika::shotgun;
 use migration::{Migrator, MigratorTrait};
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
-use std::{process, time::Duration};
-use tokio::{signal::ctrl_c, task::JoinSet};
+use std::{env, process, sync::LazyLock};
+use tokio::{signal::ctrl_c, task::JoinSet, time};
 
 mod api;
 mod cleanup;
@@ -13,7 +13,14 @@ mod encryption;
 mod error;
 mod file;
 mod hash;
+mod ip;
+mod recipient;
 mod request;
+mod rewrap;
+mod shares;
+mod storage;
+#[cfg(test)]
+mod test_util;
 mod util;
 
 #[tokio::main]
@@ -23,6 +30,26 @@ async fn main() {
     /* Init configuration */
     let connection_string = &CONFIGURATION.connection_string;
 
+    /* Rotate master keys instead of serving, if requested */
+    if env::args().nth(1).as_deref() == Some(rewrap::COMMAND) {
+        process::exit(run_rewrap(connection_string).await);
+    }
+
+    /* Benchmark hashing */
+    match hash::Hash::benchmark() {
+        Ok(duration) => log::info!(
+            "Hashing with configured Argon2 parameters takes {} ms",
+            duration.as_millis()
+        ),
+        Err(error) => {
+            log::error!("Could not hash with configured Argon2 parameters: {error:?}. Bye.");
+            process::exit(1);
+        }
+    }
+
+    /* Init storage backend */
+    LazyLock::force(&storage::STORAGE);
+
     let database_connection = match setup_database(connection_string).await {
         Some(database_connection) => database_connection,
         None => {
@@ -76,23 +103,76 @@ async fn main() {
     log::info!("Bye.");
 }
 
+/// Runs the offline command to re-wrap storage keys, see [`rewrap`]
+///
+/// # Returns
+///
+/// * Exit code of the process
+async fn run_rewrap(connection_string: &str) -> i32 {
+    let Some(database_connection) = setup_database(connection_string).await else {
+        log::error!("Bye.");
+        return 1;
+    };
+
+    let exit_code = match rewrap::run(&database_connection).await {
+        Ok(0) => {
+            log::info!("Re-wrapped all storage keys");
+            0
+        }
+        Ok(failed) => {
+            log::error!("Could not re-wrap storage keys of {failed} files");
+            1
+        }
+        Err(error) => {
+            log::error!("Re-wrapping storage keys failed: {error:?}");
+            1
+        }
+    };
+
+    if let Err(error) = database_connection.close().await {
+        log::error!("Could not close database connection: {error}");
+    }
+
+    exit_code
+}
+
 async fn setup_database(connection_string: &str) -> Option<DatabaseConnection> {
+    let pool = &CONFIGURATION.database_pool;
     let mut connect_options = ConnectOptions::new(connection_string);
 
-    log::info!("Connecting and setting up database (connection timeout is 8 secs)...");
+    log::info!(
+        "Connecting and setting up database (connection timeout is {} secs)...",
+        pool.connect_timeout.as_secs()
+    );
 
     connect_options
-        .sqlx_logging_level(log::LevelFilter::Debug)
-        .max_connections(5)
-        .min_connections(1)
-        .connect_timeout(Duration::from_secs(8))
-        .acquire_timeout(Duration::from_secs(8))
-        .idle_timeout(Duration::from_secs(8))
-        .max_lifetime(Duration::from_secs(8));
-
-    let Ok(database_connection) = Database::connect(connect_options).await else {
-        log::error!("Could not connect to database. Bye.");
-        return None;
+        .sqlx_logging_level(pool.log_level)
+        .max_connections(pool.max_connections)
+        .min_connections(pool.min_connections)
+        .connect_timeout(pool.connect_timeout)
+        .acquire_timeout(pool.acquire_timeout)
+        .idle_timeout(pool.idle_timeout)
+        .max_lifetime(pool.max_lifetime);
+
+    let mut retry = 0;
+
+    let database_connection = loop {
+        match Database::connect(connect_options.clone()).await {
+            Ok(database_connection) => break database_connection,
+            Err(error) if retry < pool.connect_retries => {
+                let delay = util::retry_delay(pool.retry_delay, retry);
+                log::warn!(
+                    "Could not connect to database: {error}. Retrying in {} secs...",
+                    delay.as_secs()
+                );
+                time::sleep(delay).await;
+                retry += 1;
+            }
+            Err(error) => {
+                log::error!("Could not connect to database: {error}. Bye.");
+                return None;
+            }
+        }
     };
 
     if Migrator::up(&database_connection, None).await.is_err() {
@@ -0,0 +1,334 @@
+//! Module containing functions for sharing files with recipients
+//!
+//! Instead of handing out a key, the encryption key of a file can be wrapped
+//! for one or more recipients, identified by their X25519 public keys (similar
+//! to age). For every recipient, an ephemeral key pair is generated and the
+//! encryption key is wrapped with a key derived from the key agreement of the
+//! ephemeral private key and the public key of the recipient.
+//!
+//! To download a file, a recipient proves possession of its private key by
+//! answering a challenge, which is the public key of another ephemeral key
+//! pair. The response is the result of the key agreement of the private key
+//! of the recipient and the challenge. Along with that, the recipient sends
+//! the shared secret of the key agreement with the ephemeral public key of
+//! its wrapped key, so that the encryption key can be unwrapped.
+
+use crate::database;
+use crate::encryption::{CipherId, Context};
+use crate::error::{Error, Result};
+use argon2::password_hash::rand_core::OsRng;
+use base64::prelude::BASE64_URL_SAFE;
+use base64::Engine;
+use hkdf::Hkdf;
+use sea_orm::DatabaseConnection;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Max number of recipients of a single file
+pub const MAX_RECIPIENTS: usize = 16;
+
+/// Length of keys in bytes
+const KEY_LENGTH: usize = 32;
+
+/// Info that wrapping keys are derived with
+const WRAPPING_KEY_INFO: &[u8] = b"treasure_chest recipient wrapping key";
+
+/// Encryption key of a file, wrapped for a single recipient
+pub struct RecipientKey {
+    /// X25519 public key of the recipient
+    pub public_key: Vec<u8>,
+    /// Public key of the ephemeral key pair used for wrapping
+    pub ephemeral_public_key: Vec<u8>,
+    /// Encoded, encrypted encryption key
+    pub wrapped_key: Vec<u8>,
+}
+
+impl RecipientKey {
+    /// Wraps encryption `key` of a file for the recipient with given
+    /// `public_key`
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Encryption key of the file
+    /// * `public_key` - X25519 public key of the recipient
+    /// * `id` - File id
+    /// * `cipher` - Cipher the file is encrypted with
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<RecipientKey>`] on success
+    /// * [`Err<Error>`] on error, e.g. if `public_key` is invalid
+    pub fn wrap(key: &[u8], public_key: &[u8], id: &Uuid, cipher: CipherId) -> Result<Self> {
+        let recipient_public_key = to_public_key(public_key)?;
+
+        let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public_key = PublicKey::from(&ephemeral_secret);
+
+        let shared_secret = ephemeral_secret.diffie_hellman(&recipient_public_key);
+        if !shared_secret.was_contributory() {
+            return Err(Error::KeyInvalid);
+        }
+
+        let wrapping_key = derive_wrapping_key(
+            shared_secret.as_bytes(),
+            ephemeral_public_key.as_bytes(),
+            public_key,
+        )?;
+
+        let wrapped_key =
+            cipher.encrypt_with_key(key.iter().copied(), &wrapping_key, &Context::key(*id))?;
+
+        Ok(Self {
+            public_key: public_key.to_vec(),
+            ephemeral_public_key: ephemeral_public_key.as_bytes().to_vec(),
+            wrapped_key,
+        })
+    }
+}
+
+/// Creates a new challenge for a recipient
+///
+/// # Returns
+///
+/// * (Secret of the challenge to keep, challenge to hand out)
+pub fn create_challenge() -> (Vec<u8>, Vec<u8>) {
+    let secret = StaticSecret::random_from_rng(OsRng);
+    let challenge = PublicKey::from(&secret);
+
+    (secret.to_bytes().to_vec(), challenge.as_bytes().to_vec())
+}
+
+/// Checks whether `response` of a recipient to a challenge is valid
+///
+/// # Arguments
+///
+/// * `challenge_secret` - Secret of the challenge
+/// * `public_key` - X25519 public key of the recipient
+/// * `response` - Response of the recipient
+///
+/// # Returns
+///
+/// * `true` if the recipient proved possession of its private key
+/// * `false` otherwise
+pub fn is_response_valid(challenge_secret: &[u8], public_key: &[u8], response: &[u8]) -> bool {
+    let (Ok(challenge_secret), Ok(public_key)) = (
+        <[u8; KEY_LENGTH]>::try_from(challenge_secret),
+        to_public_key(public_key),
+    ) else {
+        return false;
+    };
+
+    let expected = StaticSecret::from(challenge_secret).diffie_hellman(&public_key);
+
+    expected.was_contributory() && bool::from(expected.as_bytes().ct_eq(response))
+}
+
+/// Unwraps the encryption key of a file that has been wrapped for given
+/// `recipient`
+///
+/// # Arguments
+///
+/// * `recipient` - Recipient to unwrap encryption key of
+/// * `shared_secret` - Shared secret of the key agreement of the recipient
+///   with the ephemeral public key
+/// * `cipher` - Cipher the file has been encrypted with
+///
+/// # Returns
+///
+/// * [`Ok<Vec<u8>>`] on success, containing the encryption key
+/// * [`Err<Error>`] on error
+pub fn unwrap(
+    recipient: &entity::recipient::Model,
+    shared_secret: &[u8],
+    cipher: CipherId,
+) -> Result<Vec<u8>> {
+    let id = Uuid::from_slice(&recipient.file_id).map_err(|_| Error::KeyInvalid)?;
+
+    let wrapping_key = derive_wrapping_key(
+        shared_secret,
+        &recipient.ephemeral_public_key,
+        &recipient.public_key,
+    )?;
+
+    cipher
+        .decrypt(
+            recipient.wrapped_key.clone(),
+            &wrapping_key,
+            &Context::key(id),
+        )
+        .map_err(|_| Error::KeyInvalid)
+}
+
+/// Validates the response of a recipient to its challenge and unwraps the
+/// encryption key of the file on success
+///
+/// The challenge is consumed, so that every challenge can only be answered
+/// once.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+/// * `file` - File to get encryption key of
+/// * `encoded_public_key` - Encoded X25519 public key of the recipient
+/// * `encoded_response` - Encoded response of the recipient to its challenge
+/// * `encoded_shared_secret` - Encoded shared secret of the key agreement of
+///   the recipient with the ephemeral public key
+/// * `cipher` - Cipher the file has been encrypted with
+///
+/// # Returns
+///
+/// * [`Ok<Vec<u8>>`] on success, containing the encryption key
+/// * [`Err<Error>`] on error or if the response is invalid
+pub async fn get_validated_key(
+    database_connection: &DatabaseConnection,
+    file: &entity::file::Model,
+    encoded_public_key: &str,
+    encoded_response: Option<&str>,
+    encoded_shared_secret: &str,
+    cipher: CipherId,
+) -> Result<Vec<u8>> {
+    let decode = |encoded: &str| {
+        BASE64_URL_SAFE
+            .decode(encoded)
+            .map_err(|_| Error::KeyInvalid)
+    };
+
+    let public_key = decode(encoded_public_key)?;
+    let response = decode(encoded_response.ok_or(Error::KeyInvalid)?)?;
+    let shared_secret = decode(encoded_shared_secret)?;
+
+    let id = Uuid::from_slice(&file.id).map_err(|_| Error::KeyInvalid)?;
+
+    let recipient = database::take_challenge(database_connection, &id, &public_key)
+        .await?
+        .ok_or(Error::KeyInvalid)?;
+
+    let challenge_secret = recipient
+        .challenge_secret
+        .as_deref()
+        .ok_or(Error::KeyInvalid)?;
+
+    if !is_response_valid(challenge_secret, &public_key, &response) {
+        return Err(Error::KeyInvalid);
+    }
+
+    unwrap(&recipient, &shared_secret, cipher)
+}
+
+/// Converts given bytes into an X25519 public key
+///
+/// # Returns
+///
+/// * [`Ok<PublicKey>`] on success
+/// * [`Err<Error>`] if `public_key` has an invalid length
+fn to_public_key(public_key: &[u8]) -> Result<PublicKey> {
+    <[u8; KEY_LENGTH]>::try_from(public_key)
+        .map(PublicKey::from)
+        .map_err(|_| Error::KeyInvalid)
+}
+
+/// Derives the key that the encryption key is wrapped with for a recipient
+///
+/// # Arguments
+///
+/// * `shared_secret` - Shared secret of the key agreement
+/// * `ephemeral_public_key` - Public key of the ephemeral key pair
+/// * `public_key` - Public key of the recipient
+///
+/// # Returns
+///
+/// * [`Ok<Vec<u8>>`] on success, containing the wrapping key
+/// * [`Err<Error>`] on error
+fn derive_wrapping_key(
+    shared_secret: &[u8],
+    ephemeral_public_key: &[u8],
+    public_key: &[u8],
+) -> Result<Vec<u8>> {
+    let salt = [ephemeral_public_key, public_key].concat();
+    let mut wrapping_key = vec![0u8; KEY_LENGTH];
+
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(WRAPPING_KEY_INFO, &mut wrapping_key)
+        .map_err(|_| Error::EncryptionFailed)?;
+
+    Ok(wrapping_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_recipient(id: &Uuid, recipient_key: RecipientKey) -> entity::recipient::Model {
+        entity::recipient::Model {
+            id: Uuid::new_v4().into(),
+            file_id: id.as_bytes().to_vec(),
+            public_key: recipient_key.public_key,
+            ephemeral_public_key: recipient_key.ephemeral_public_key,
+            wrapped_key: recipient_key.wrapped_key,
+            challenge_secret: None,
+            challenge_until: None,
+        }
+    }
+
+    #[test]
+    fn key_unwrapped_by_recipient_only() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::default();
+        let key = [7; 32];
+
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public_key = PublicKey::from(&secret);
+        let other_secret = StaticSecret::random_from_rng(OsRng);
+
+        let recipient_key = RecipientKey::wrap(&key, public_key.as_bytes(), &id, cipher).unwrap();
+        let recipient = create_recipient(&id, recipient_key);
+
+        let ephemeral_public_key = to_public_key(&recipient.ephemeral_public_key).unwrap();
+        let shared_secret = secret.diffie_hellman(&ephemeral_public_key);
+        let other_shared_secret = other_secret.diffie_hellman(&ephemeral_public_key);
+
+        let unwrapped = unwrap(&recipient, shared_secret.as_bytes(), cipher);
+        assert_eq!(key.to_vec(), unwrapped.unwrap());
+
+        assert!(unwrap(&recipient, other_shared_secret.as_bytes(), cipher).is_err());
+    }
+
+    #[test]
+    fn challenge_answered_by_recipient_only() {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public_key = PublicKey::from(&secret);
+        let other_secret = StaticSecret::random_from_rng(OsRng);
+
+        let (challenge_secret, challenge) = create_challenge();
+        let challenge = to_public_key(&challenge).unwrap();
+
+        let response = secret.diffie_hellman(&challenge);
+        let other_response = other_secret.diffie_hellman(&challenge);
+
+        assert!(is_response_valid(
+            &challenge_secret,
+            public_key.as_bytes(),
+            response.as_bytes()
+        ));
+        assert!(!is_response_valid(
+            &challenge_secret,
+            public_key.as_bytes(),
+            other_response.as_bytes()
+        ));
+        assert!(!is_response_valid(
+            &challenge_secret,
+            public_key.as_bytes(),
+            &[]
+        ));
+    }
+
+    #[test]
+    fn invalid_public_key_not_wrapped_for() {
+        let id = Uuid::new_v4();
+
+        assert!(RecipientKey::wrap(&[7; 32], &[1, 2, 3], &id, CipherId::default()).is_err());
+        assert!(RecipientKey::wrap(&[7; 32], &[0; 32], &id, CipherId::default()).is_err());
+    }
+}
@@ -3,22 +3,36 @@
 use super::error::{Error, Result};
 use crate::configuration::CONFIGURATION;
 use crate::file;
-use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use crate::ip;
+use crate::recipient;
+use axum::http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE};
 use axum::http::HeaderMap;
+use base64::prelude::BASE64_URL_SAFE;
+use base64::Engine;
 use regex::Regex;
 use std::sync::LazyLock;
 use uuid::Uuid;
 
 const FALLBACK_CONTENT_TYPE: &str = "application/octet-stream";
 
+/// Name of header containing the passphrase to protect an uploaded file with
+const PASSPHRASE_HEADER_NAME: &str = "X-Passphrase";
+
+/// Name of header containing the verifier hash of a client-encrypted file
+const VERIFIER_HASH_HEADER_NAME: &str = "X-Verifier-Hash";
+
+/// Name of header containing the public keys of the recipients of a file
+const RECIPIENTS_HEADER_NAME: &str = "X-Recipients";
+
 static FILE_NAME_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new("filename=\"(.*?)\"").unwrap());
 
 /// Tries getting request Ip from given `headers`
 ///
 /// The header name defined in [`CONFIGURATION`] will be checked for an (Ip)
-/// value and then returned. If the value is missing / empty, an [`Error`] is
-/// returned.
+/// value. The Ip of the client is taken from the configured position of the
+/// value, see [`ip::get_client_ip`], anonymized as configured and then
+/// returned. If the value is missing / invalid, an [`Error`] is returned.
 ///
 /// # Arguments
 ///
@@ -26,15 +40,120 @@ static FILE_NAME_REGEX: LazyLock<Regex> =
 ///
 /// # Returns
 ///
-/// * [`Ok<String>`] on success, containing the request Ip  
+/// * [`Ok<String>`] on success, containing the (anonymized) request Ip  
 /// * [`Err<Error>`] on error
 pub fn get_request_ip(headers: &HeaderMap) -> Result<String> {
-    Ok(headers
+    let ip = headers
         .get(CONFIGURATION.ip_header_name.clone())
         .ok_or(Error::IpHeaderMissing(CONFIGURATION.ip_header_name.clone()))?
         .to_str()
-        .map_err(|_| Error::IpHeaderInvalid)?
-        .to_string())
+        .map_err(|_| Error::IpHeaderInvalid)?;
+
+    let ip = ip::get_client_ip(ip, CONFIGURATION.ip_header_position)?;
+
+    ip::anonymize(ip, &CONFIGURATION.ip_anonymization)
+}
+
+/// Tries getting bearer token from `Authorization` header of given `headers`
+///
+/// # Arguments
+///
+/// * `headers` - Headers to check
+///
+/// # Returns
+///
+/// * [`Some<&str>`] containing the (still encoded) token
+/// * [`None`] if header is missing or isn't a bearer token
+pub fn get_bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Tries getting passphrase to protect an uploaded file with from given
+/// `headers`
+///
+/// # Arguments
+///
+/// * `headers` - Headers to check
+///
+/// # Returns
+///
+/// * [`Some<String>`] containing the passphrase
+/// * [`None`] if header is missing or empty
+pub fn get_passphrase(headers: &HeaderMap) -> Option<String> {
+    get_non_empty_header(headers, PASSPHRASE_HEADER_NAME)
+}
+
+/// Tries getting verifier hash of a client-encrypted file from given
+/// `headers`
+///
+/// The verifier hash is checked against the token that clients send to
+/// download the file, as the server never learns the actual key.
+///
+/// # Arguments
+///
+/// * `headers` - Headers to check
+///
+/// # Returns
+///
+/// * [`Some<String>`] containing the verifier hash
+/// * [`None`] if header is missing or empty
+pub fn get_verifier_hash(headers: &HeaderMap) -> Option<String> {
+    get_non_empty_header(headers, VERIFIER_HASH_HEADER_NAME)
+}
+
+/// Tries getting public keys of the recipients of an uploaded file from given
+/// `headers`
+///
+/// Public keys are given comma-separated and URL-safe base64 encoded.
+///
+/// # Arguments
+///
+/// * `headers` - Headers to check
+///
+/// # Returns
+///
+/// * [`Ok<Vec<Vec<u8>>>`] containing the decoded public keys, empty if header
+///   is missing
+/// * [`Err<Error>`] if a public key can't be decoded or there are too many
+pub fn get_recipients(headers: &HeaderMap) -> Result<Vec<Vec<u8>>> {
+    let Some(recipients) = get_non_empty_header(headers, RECIPIENTS_HEADER_NAME) else {
+        return Ok(vec![]);
+    };
+
+    let public_keys = recipients
+        .split(',')
+        .map(|public_key| {
+            BASE64_URL_SAFE
+                .decode(public_key.trim())
+                .map_err(|_| Error::KeyInvalid)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if public_keys.len() > recipient::MAX_RECIPIENTS {
+        return Err(Error::KeyInvalid);
+    }
+
+    Ok(public_keys)
+}
+
+/// Tries getting value of header with given `name` from `headers`
+///
+/// # Returns
+///
+/// * [`Some<String>`] containing the header value
+/// * [`None`] if header is missing, empty or not valid text
+fn get_non_empty_header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(String::from)
 }
 
 impl From<file::Metadata> for HeaderMap {
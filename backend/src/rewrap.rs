@@ -0,0 +1,138 @@
+//! Offline command to rotate master keys
+//!
+//! Storage keys of all files that are wrapped with another master key than
+//! the current one are unwrapped and wrapped with the current master key
+//! again. Stored data of files isn't touched. Once the command succeeded,
+//! old master keys can be removed from the configuration.
+
+use crate::database;
+use crate::encryption::master_key::{self, MasterKey, WrappedStorageKey};
+use crate::encryption::CipherId;
+use crate::error::{Error, Result};
+use sea_orm::DatabaseConnection;
+use uuid::Uuid;
+
+/// Command line argument that runs this command instead of the server
+pub const COMMAND: &str = "rewrap";
+
+/// Re-wraps the storage keys of all files with the current master key
+///
+/// Files whose storage key can't be re-wrapped are logged and skipped.
+///
+/// # Arguments
+///
+/// * `database_connection` - [`DatabaseConnection`] to use
+///
+/// # Returns
+///
+/// * [`Ok<usize>`] containing the number of files that could not be
+///   re-wrapped
+/// * [`Err<Error>`] on error, e.g. if no master key is configured
+pub async fn run(database_connection: &DatabaseConnection) -> Result<usize> {
+    let current = master_key::current().ok_or(Error::InvalidMasterKeyConfiguration(
+        "No master key configured".into(),
+    ))?;
+
+    let files = database::get_files_to_rewrap(database_connection, current.id).await?;
+    log::info!(
+        "Re-wrapping storage keys of {} files with master key {}...",
+        files.len(),
+        current.id
+    );
+
+    let mut failed = 0;
+
+    for file in files {
+        let Ok(id) = Uuid::from_slice(&file.id) else {
+            failed += 1;
+            continue;
+        };
+
+        let result = match file.master_key_id.map(master_key::get) {
+            Some(Ok(old)) => rewrap_storage_key(&file, old, current),
+            Some(Err(error)) => Err(error),
+            None => continue,
+        };
+
+        let result = match result {
+            Ok(storage_key) => {
+                database::update_storage_key(database_connection, &id, storage_key).await
+            }
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            log::error!("Could not re-wrap storage key of file {id}: {error:?}");
+            failed += 1;
+        }
+    }
+
+    Ok(failed)
+}
+
+/// Unwraps the storage key of given `file` with master key `old` and wraps
+/// it with master key `new`
+///
+/// # Arguments
+///
+/// * `file` - File to re-wrap storage key of
+/// * `old` - Master key the storage key is currently wrapped with
+/// * `new` - Master key to wrap storage key with
+///
+/// # Returns
+///
+/// * [`Ok<WrappedStorageKey>`] on success
+/// * [`Err<Error>`] on error
+fn rewrap_storage_key(
+    file: &entity::file::Model,
+    old: &MasterKey,
+    new: &MasterKey,
+) -> Result<WrappedStorageKey> {
+    let id = Uuid::from_slice(&file.id).map_err(|_| Error::KeyInvalid)?;
+    let cipher = CipherId::try_from(file.cipher)?;
+    let wrapped_key = file
+        .wrapped_storage_key
+        .as_deref()
+        .ok_or(Error::KeyInvalid)?;
+
+    let storage_key = old.unwrap(wrapped_key, &id, cipher)?;
+
+    new.wrap(&storage_key, &id, cipher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn storage_key_rewrapped() {
+        let id = Uuid::new_v4();
+        let cipher = CipherId::Aes256GcmSiv;
+        let old = MasterKey {
+            id: 1,
+            key: vec![1; 32],
+        };
+        let new = MasterKey {
+            id: 2,
+            key: vec![2; 32],
+        };
+
+        let wrapped = old.wrap(&[7; 32], &id, cipher).unwrap();
+
+        let file = entity::file::Model {
+            cipher: cipher as i16,
+            master_key_id: Some(wrapped.master_key_id),
+            wrapped_storage_key: Some(wrapped.wrapped_key),
+            ..test_util::file_model(&id)
+        };
+
+        let rewrapped = rewrap_storage_key(&file, &old, &new).unwrap();
+        assert_eq!(2, rewrapped.master_key_id);
+
+        let storage_key = new.unwrap(&rewrapped.wrapped_key, &id, cipher);
+        assert_eq!(vec![7; 32], storage_key.unwrap());
+
+        assert!(rewrap_storage_key(&file, &new, &old).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Module containing functions for splitting keys into shares
+//!
+//! Instead of handing out a single key, the key of a file can be split into
+//! a number of shares via Shamir's secret sharing, so that it can only be
+//! reconstructed by a threshold of share holders together. Reconstructed keys
+//! are validated against the hash of the file like any other key, so wrong or
+//! too few shares count as failed attempts.
+
+use crate::error::{Error, Result};
+use base64::prelude::BASE64_URL_SAFE;
+use base64::Engine;
+use blahaj::{Share, Sharks};
+
+/// Max number of shares a key can be split into
+pub const MAX_SHARES: u8 = 16;
+
+/// Checks whether a key can be split into `count` shares, of which
+/// `threshold` shares are required to reconstruct it
+///
+/// # Returns
+///
+/// * `true` if `threshold` and `count` are valid
+/// * `false` otherwise
+pub fn is_valid(threshold: u8, count: u8) -> bool {
+    (2..=count).contains(&threshold) && count <= MAX_SHARES
+}
+
+/// Splits given `key` into `count` shares, of which `threshold` shares are
+/// required to reconstruct it. `threshold` and `count` must be valid, see
+/// [`is_valid`].
+///
+/// # Arguments
+///
+/// * `key` - Key to split
+/// * `threshold` - Number of shares required to reconstruct `key`
+/// * `count` - Number of shares to create
+///
+/// # Returns
+///
+/// * Encoded shares
+pub fn split(key: &[u8], threshold: u8, count: u8) -> Vec<Vec<u8>> {
+    Sharks(threshold)
+        .dealer(key)
+        .take(count.into())
+        .map(|share| Vec::from(&share))
+        .collect()
+}
+
+/// Reconstructs a key from given, base64 encoded shares
+///
+/// All given shares are used for reconstruction, so if fewer shares than
+/// required are given, a wrong key is reconstructed.
+///
+/// # Arguments
+///
+/// * `encoded_shares` - Base64 encoded shares
+///
+/// # Returns
+///
+/// * [`Ok<String>`] on success, containing the base64 encoded key
+/// * [`Err<Error>`] if shares are invalid
+pub fn combine(encoded_shares: &[String]) -> Result<String> {
+    let count = u8::try_from(encoded_shares.len())
+        .ok()
+        .filter(|count| is_valid(2, *count))
+        .ok_or(Error::KeyInvalid)?;
+
+    let shares = encoded_shares
+        .iter()
+        .map(|encoded_share| {
+            let share = BASE64_URL_SAFE
+                .decode(encoded_share)
+                .map_err(|_| Error::KeyInvalid)?;
+
+            Share::try_from(share.as_slice()).map_err(|_| Error::KeyInvalid)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    /* Fails on duplicate shares, as they don't count towards the threshold */
+    Sharks(count)
+        .recover(&shares)
+        .map(|key| BASE64_URL_SAFE.encode(key))
+        .map_err(|_| Error::KeyInvalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(shares: &[Vec<u8>]) -> Vec<String> {
+        shares
+            .iter()
+            .map(|share| BASE64_URL_SAFE.encode(share))
+            .collect()
+    }
+
+    #[test]
+    fn key_reconstructed_from_threshold_of_shares() {
+        let key = [7; 32];
+        let shares = encode(&split(&key, 3, 5));
+        assert_eq!(5, shares.len());
+
+        let encoded_key = BASE64_URL_SAFE.encode(key);
+        assert_eq!(encoded_key, combine(&shares[..3]).unwrap());
+        assert_eq!(encoded_key, combine(&shares[1..]).unwrap());
+        assert_ne!(encoded_key, combine(&shares[..2]).unwrap());
+    }
+
+    #[test]
+    fn invalid_shares_not_combined() {
+        let shares = encode(&split(&[7; 32], 2, 3));
+
+        assert!(combine(&shares[..1]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone()]).is_err());
+        assert!(combine(&[shares[0].clone(), "@@@".into()]).is_err());
+        assert!(combine(&vec![shares[0].clone(); 17]).is_err());
+    }
+
+    #[test]
+    fn invalid_parameters_detected() {
+        assert!(is_valid(2, 2));
+        assert!(is_valid(3, MAX_SHARES));
+        assert!(!is_valid(1, 3));
+        assert!(!is_valid(4, 3));
+        assert!(!is_valid(2, MAX_SHARES + 1));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::error::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+/// Boxed reader of stored data
+pub type DataReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Provides functions to store, load and delete encrypted data of files.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Stores new data
+    ///
+    /// `content` is stored chunk by chunk while it is being polled. If
+    /// `content` yields an error, already stored data is removed and the error
+    /// is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - File id
+    /// * `content` - Stream of content to store
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<()>`] on success
+    /// * [`Err<Error>`] on error
+    async fn store(&self, id: &Uuid, content: BoxStream<'_, Result<Vec<u8>>>) -> Result<()>;
+
+    /// Opens stored data for reading
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - File id
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<DataReader>`] on success, containing reader of stored data
+    /// * [`Err<Error>`] on error
+    async fn load(&self, id: &Uuid) -> Result<DataReader>;
+
+    /// Ensures data is deleted
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - File id
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<()>`] ensuring data doesn't exist (anymore)
+    /// * [`Err<Error>`] on error
+    async fn delete(&self, id: &Uuid) -> Result<()>;
+
+    /// Retrieves the Ids of all stored files
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<Vec<Uuid>>`] containing the Ids of all stored files
+    /// * [`Err<Error>`] on error
+    async fn stored_ids(&self) -> Result<Vec<Uuid>>;
+
+    /// Removes data that was left over by storing operations that have never
+    /// been completed (e.g. because of a crash)
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - Age of left over data after which it's considered stale
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<()>`] on success
+    /// * [`Err<Error>`] on error
+    async fn purge_stale_staged(&self, _max_age: Duration) -> Result<()> {
+        Ok(())
+    }
+}
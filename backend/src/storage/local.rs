@@ -0,0 +1,292 @@
+use super::definitions::{DataReader, StorageBackend};
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// Suffix of staging files, which contain data that is still being written.
+const STAGING_SUFFIX: &str = ".partial";
+
+/// Storage backend that stores data as files in a local directory.
+pub struct LocalStorage {
+    /// Directory to store files in
+    path: PathBuf,
+}
+
+impl LocalStorage {
+    /// Creates new [`LocalStorage`] storing files in `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Returns the path of the file with given `id`
+    fn file_path(&self, id: &Uuid) -> PathBuf {
+        self.path.join(id.to_string())
+    }
+
+    /// Returns the path of the staging file with given `id`, which is used
+    /// while the file is being written
+    fn staging_path(&self, id: &Uuid) -> PathBuf {
+        self.path.join(format!(".{id}{STAGING_SUFFIX}"))
+    }
+}
+
+/// Writes `content` to a new file at `path` and syncs it to disk.
+///
+/// If writing fails, the partially written file is removed.
+///
+/// # Arguments
+///
+/// * `path` - Path of new file
+/// * `content` - Stream of content to write
+///
+/// # Returns
+///
+/// * [`Ok<()>`] on success
+/// * [`Err<Error>`] on error
+async fn write_synced(path: &Path, mut content: BoxStream<'_, Result<Vec<u8>>>) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)
+        .await
+        .map_err(Error::SavingFileFailed)?;
+
+    while let Some(chunk) = content.next().await {
+        let result = match chunk {
+            Ok(chunk) => file
+                .write_all(&chunk)
+                .await
+                .map_err(Error::SavingFileFailed),
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            drop(file);
+            remove_if_exists(path).await?;
+            return Err(error);
+        }
+    }
+
+    if let Err(error) = file.sync_all().await {
+        drop(file);
+        remove_if_exists(path).await?;
+        return Err(Error::SavingFileFailed(error));
+    }
+
+    Ok(())
+}
+
+/// Removes file at `path` if it exists
+///
+/// # Arguments
+///
+/// * `path` - Path of file to remove
+///
+/// # Returns
+///
+/// * [`Ok<()>`] ensuring file doesn't exist (anymore)
+/// * [`Err<Error>`] on error
+async fn remove_if_exists(path: &Path) -> Result<()> {
+    if !(tokio::fs::try_exists(path)
+        .await
+        .map_err(Error::DeletingFileFailed)?)
+    {
+        return Ok(());
+    }
+
+    if !tokio::fs::metadata(path)
+        .await
+        .map_err(Error::DeletingFileFailed)?
+        .is_file()
+    {
+        return Err(Error::DeletingFileFailed(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            "Directory given",
+        )));
+    }
+
+    tokio::fs::remove_file(path)
+        .await
+        .map_err(Error::DeletingFileFailed)
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn store(&self, id: &Uuid, content: BoxStream<'_, Result<Vec<u8>>>) -> Result<()> {
+        let staging_path = self.staging_path(id);
+
+        /* Data is written to a staging file first and only renamed to its final
+         * name once it's complete, so a crash never leaves a truncated file. */
+        write_synced(&staging_path, content).await?;
+
+        if let Err(error) = tokio::fs::rename(&staging_path, self.file_path(id)).await {
+            remove_if_exists(&staging_path).await?;
+            return Err(Error::SavingFileFailed(error));
+        }
+
+        /* Persist rename */
+        tokio::fs::File::open(&self.path)
+            .await
+            .map_err(Error::SavingFileFailed)?
+            .sync_all()
+            .await
+            .map_err(Error::SavingFileFailed)
+    }
+
+    async fn load(&self, id: &Uuid) -> Result<DataReader> {
+        let file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .open(self.file_path(id))
+            .await
+            .map_err(Error::LoadingFileFailed)?;
+
+        Ok(Box::new(file))
+    }
+
+    async fn delete(&self, id: &Uuid) -> Result<()> {
+        remove_if_exists(&self.file_path(id)).await
+    }
+
+    async fn stored_ids(&self) -> Result<Vec<Uuid>> {
+        let mut file_ids = vec![];
+
+        let mut read_dir = tokio::fs::read_dir(&self.path)
+            .await
+            .map_err(Error::ReadingDirectoryFailed)?;
+
+        while let Some(dir_entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(Error::ReadingDirectoryFailed)?
+        {
+            let file_name = dir_entry.file_name();
+
+            let file_name =
+                file_name
+                    .to_str()
+                    .ok_or(Error::ReadingDirectoryFailed(io::Error::other(
+                        "Could not get file name",
+                    )))?;
+
+            if file_name.ends_with(STAGING_SUFFIX) {
+                continue;
+            }
+
+            let file_id = Uuid::from_str(file_name).map_err(|_| {
+                Error::ReadingDirectoryFailed(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("File name not a Uuid: {file_name}"),
+                ))
+            })?;
+
+            file_ids.push(file_id);
+        }
+
+        Ok(file_ids)
+    }
+
+    async fn purge_stale_staged(&self, max_age: Duration) -> Result<()> {
+        let mut read_dir = tokio::fs::read_dir(&self.path)
+            .await
+            .map_err(Error::ReadingDirectoryFailed)?;
+
+        while let Some(dir_entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(Error::ReadingDirectoryFailed)?
+        {
+            if !dir_entry
+                .file_name()
+                .to_str()
+                .is_some_and(|file_name| file_name.ends_with(STAGING_SUFFIX))
+            {
+                continue;
+            }
+
+            /* Staging files are modified continuously while being written */
+            let age = dir_entry
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .map_err(Error::ReadingDirectoryFailed)?
+                .elapsed()
+                .unwrap_or_default();
+
+            if age >= max_age {
+                remove_if_exists(&dir_entry.path()).await?;
+                log::info!("Deleted stale staging file: {:?}", dir_entry.file_name());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::fs;
+
+    fn create_storage() -> (LocalStorage, PathBuf) {
+        let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir(&path).unwrap();
+
+        (LocalStorage::new(path.clone()), path)
+    }
+
+    #[tokio::test]
+    async fn stored_without_staging_file() {
+        let (storage, path) = create_storage();
+        let id = Uuid::new_v4();
+
+        let content = stream::iter(vec![Ok(vec![1, 2]), Ok(vec![3])]).boxed();
+        storage.store(&id, content).await.unwrap();
+
+        assert_eq!(vec![id], storage.stored_ids().await.unwrap());
+        assert_eq!(vec![1, 2, 3], fs::read(path.join(id.to_string())).unwrap());
+        assert_eq!(1, fs::read_dir(&path).unwrap().count());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_store_leaves_no_file() {
+        let (storage, path) = create_storage();
+        let id = Uuid::new_v4();
+
+        let content = stream::iter(vec![Ok(vec![1, 2]), Err(Error::EncryptionFailed)]).boxed();
+        assert!(storage.store(&id, content).await.is_err());
+
+        assert_eq!(0, fs::read_dir(&path).unwrap().count());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_staging_file_purged() {
+        let (storage, path) = create_storage();
+        let id = Uuid::new_v4();
+
+        fs::write(storage.staging_path(&id), [1, 2, 3]).unwrap();
+        assert!(storage.stored_ids().await.unwrap().is_empty());
+
+        storage
+            .purge_stale_staged(Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(1, fs::read_dir(&path).unwrap().count());
+
+        storage.purge_stale_staged(Duration::ZERO).await.unwrap();
+        assert_eq!(0, fs::read_dir(&path).unwrap().count());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+//! Storage module.
+//!
+//! This module provides storage backends for encrypted file data. It includes
+//! submodules for storage definitions, a local-filesystem backend and an
+//! S3-compatible backend. The backend in use is chosen in the configuration.
+mod definitions;
+mod local;
+mod s3;
+
+use crate::configuration::{StorageConfiguration, CONFIGURATION};
+use std::{process::exit, sync::LazyLock};
+
+pub use definitions::*;
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+pub static STORAGE: LazyLock<Box<dyn StorageBackend>> = LazyLock::new(build);
+
+/// Builds the [`StorageBackend`] that is configured in [`CONFIGURATION`]
+///
+/// # Returns
+///
+/// Configured [`StorageBackend`]
+///
+/// # Note
+///
+/// If storage backend is not buildable, it exits the program.
+fn build() -> Box<dyn StorageBackend> {
+    match &CONFIGURATION.storage {
+        StorageConfiguration::Local(file_path) => Box::new(LocalStorage::new(file_path.clone())),
+        StorageConfiguration::S3(configuration) => match S3Storage::new(configuration) {
+            Ok(storage) => Box::new(storage),
+            Err(error) => {
+                log::error!("Could not build storage backend: {error:?}. Bye.");
+                exit(1);
+            }
+        },
+    }
+}
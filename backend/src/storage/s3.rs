@@ -0,0 +1,280 @@
+use super::definitions::{DataReader, StorageBackend};
+use crate::configuration::S3Configuration;
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::{ObjectStore, WriteMultipart};
+use std::io;
+use std::str::FromStr;
+use tokio::runtime::Handle;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+/// Max number of parts that are uploaded concurrently while storing data.
+/// Limits memory usage to roughly this number times the part size (5 MiB).
+const MAX_CONCURRENT_PARTS: usize = 2;
+
+/// Storage backend that stores data as objects in an S3-compatible object
+/// store (e.g. AWS S3 or MinIO).
+///
+/// Data is uploaded in parts. Uploads that fail or are dropped are aborted,
+/// but uploads interrupted by a crash stay incomplete and aren't purged, as
+/// they can't be listed. The bucket needs a lifecycle rule that aborts
+/// incomplete multipart uploads to remove them.
+pub struct S3Storage {
+    /// Client of the object store
+    store: AmazonS3,
+    /// Prefix of all object keys
+    prefix: Path,
+}
+
+impl S3Storage {
+    /// Creates new [`S3Storage`] with given `configuration`
+    ///
+    /// # Arguments
+    ///
+    /// * `configuration` - Configuration of the object store to use
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok<S3Storage>`] on success
+    /// * [`Err<Error>`] on invalid configuration
+    pub fn new(configuration: &S3Configuration) -> Result<Self> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&configuration.bucket)
+            .with_region(&configuration.region)
+            .with_access_key_id(&configuration.access_key_id)
+            .with_secret_access_key(&configuration.secret_access_key)
+            .with_allow_http(configuration.allow_http);
+
+        if let Some(endpoint) = &configuration.endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+
+        let store = builder
+            .build()
+            .map_err(|error| Error::InvalidStorageConfiguration(error.to_string()))?;
+
+        Ok(Self {
+            store,
+            prefix: Path::from(configuration.prefix.as_str()),
+        })
+    }
+
+    /// Returns the object path of the file with given `id`
+    fn object_path(&self, id: &Uuid) -> Path {
+        self.prefix.child(id.to_string())
+    }
+}
+
+/// Multipart upload of a file that is aborted if it's dropped before it has
+/// been finished, e.g. because the client disconnected during the upload
+struct PendingUpload {
+    /// File id
+    id: Uuid,
+    /// Writer of the upload, taken on finishing or aborting
+    writer: Option<WriteMultipart>,
+}
+
+impl PendingUpload {
+    /// Writes given `chunk`, waiting for previous parts to be uploaded if too
+    /// many are in flight
+    async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer
+                .wait_for_capacity(MAX_CONCURRENT_PARTS)
+                .await
+                .map_err(|error| Error::SavingFileFailed(io::Error::other(error)))?;
+            writer.write(chunk);
+        }
+
+        Ok(())
+    }
+
+    /// Completes the upload, so that the object becomes visible
+    async fn finish(mut self) -> Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer
+                .finish()
+                .await
+                .map(|_| ())
+                .map_err(|error| Error::SavingFileFailed(io::Error::other(error))),
+            None => Ok(()),
+        }
+    }
+
+    /// Aborts the upload, so that its parts are removed
+    async fn abort(mut self) {
+        if let Some(writer) = self.writer.take() {
+            if let Err(error) = writer.abort().await {
+                log::error!("Could not abort upload of {}: {error}", self.id);
+            }
+        }
+    }
+}
+
+impl Drop for PendingUpload {
+    fn drop(&mut self) {
+        let (Some(writer), Ok(runtime)) = (self.writer.take(), Handle::try_current()) else {
+            return;
+        };
+
+        let id = self.id;
+
+        runtime.spawn(async move {
+            if let Err(error) = writer.abort().await {
+                log::error!("Could not abort dropped upload of {id}: {error}");
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn store(&self, id: &Uuid, mut content: BoxStream<'_, Result<Vec<u8>>>) -> Result<()> {
+        let upload = self
+            .store
+            .put_multipart(&self.object_path(id))
+            .await
+            .map_err(|error| Error::SavingFileFailed(io::Error::other(error)))?;
+
+        let mut upload = PendingUpload {
+            id: *id,
+            writer: Some(WriteMultipart::new(upload)),
+        };
+
+        while let Some(chunk) = content.next().await {
+            let result = match chunk {
+                Ok(chunk) => upload.write(&chunk).await,
+                Err(error) => Err(error),
+            };
+
+            if let Err(error) = result {
+                upload.abort().await;
+                return Err(error);
+            }
+        }
+
+        upload.finish().await
+    }
+
+    async fn load(&self, id: &Uuid) -> Result<DataReader> {
+        let content = self
+            .store
+            .get(&self.object_path(id))
+            .await
+            .map_err(|error| Error::LoadingFileFailed(io::Error::other(error)))?
+            .into_stream()
+            .map_err(io::Error::other);
+
+        Ok(Box::new(StreamReader::new(content)))
+    }
+
+    async fn delete(&self, id: &Uuid) -> Result<()> {
+        match self.store.delete(&self.object_path(id)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(error) => Err(Error::DeletingFileFailed(io::Error::other(error))),
+        }
+    }
+
+    async fn stored_ids(&self) -> Result<Vec<Uuid>> {
+        let objects = self
+            .store
+            .list(Some(&self.prefix))
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|error| Error::ReadingDirectoryFailed(io::Error::other(error)))?;
+
+        objects
+            .iter()
+            .map(|object| {
+                let file_name = object.location.filename().unwrap_or_default();
+
+                Uuid::from_str(file_name).map_err(|_| {
+                    Error::ReadingDirectoryFailed(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Object name not a Uuid: {file_name}"),
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    /// Name of env var containing the endpoint of an S3-compatible object
+    /// store to test against, e.g. a local MinIO. Tests that need it are
+    /// ignored by default, run them with `cargo test -- --ignored`.
+    const ENDPOINT_ENV_VAR: &str = "TREASURE_CHEST_TEST_S3_ENDPOINT";
+
+    /// Creates storage in a new prefix of the test object store. Bucket and
+    /// credentials default to the ones of a local MinIO and can be overridden
+    /// by env vars `TREASURE_CHEST_TEST_S3_BUCKET`, `.._ACCESS_KEY_ID` and
+    /// `.._SECRET_ACCESS_KEY`.
+    fn create_storage() -> S3Storage {
+        let endpoint = std::env::var(ENDPOINT_ENV_VAR)
+            .unwrap_or_else(|_| panic!("{ENDPOINT_ENV_VAR} must be set"));
+
+        let env_var = |name: &str, default: &str| {
+            std::env::var(format!("TREASURE_CHEST_TEST_S3_{name}")).unwrap_or(default.into())
+        };
+
+        let configuration = S3Configuration {
+            endpoint: Some(endpoint),
+            region: env_var("REGION", "us-east-1"),
+            bucket: env_var("BUCKET", "treasure-chest"),
+            prefix: Uuid::new_v4().to_string(),
+            access_key_id: env_var("ACCESS_KEY_ID", "minioadmin"),
+            secret_access_key: env_var("SECRET_ACCESS_KEY", "minioadmin"),
+            allow_http: true,
+        };
+
+        S3Storage::new(&configuration).unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible object store"]
+    async fn stored_loaded_and_deleted() {
+        let storage = create_storage();
+        let id = Uuid::new_v4();
+
+        let content = stream::iter(vec![Ok(vec![1, 2]), Ok(vec![3])]).boxed();
+        storage.store(&id, content).await.unwrap();
+        assert_eq!(vec![id], storage.stored_ids().await.unwrap());
+
+        let mut data = vec![];
+        storage
+            .load(&id)
+            .await
+            .unwrap()
+            .read_to_end(&mut data)
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], data);
+
+        storage.delete(&id).await.unwrap();
+        assert!(storage.stored_ids().await.unwrap().is_empty());
+        assert!(storage.load(&id).await.is_err());
+        storage.delete(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible object store"]
+    async fn failed_store_leaves_no_object() {
+        let storage = create_storage();
+        let id = Uuid::new_v4();
+
+        let content = stream::iter(vec![Ok(vec![1, 2]), Err(Error::EncryptionFailed)]).boxed();
+        assert!(storage.store(&id, content).await.is_err());
+
+        assert!(storage.stored_ids().await.unwrap().is_empty());
+    }
+}
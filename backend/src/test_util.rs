@@ -0,0 +1,46 @@
+//! Module with helpers that are shared by tests of several modules
+
+use crate::encryption::CipherId;
+use chrono::{Days, Utc};
+use uuid::Uuid;
+
+/// Creates a file with given `id` that has been uploaded just now, can be
+/// downloaded once within a day and isn't protected by anything but its key.
+/// Tests adjust the fields they depend on.
+///
+/// # Arguments
+///
+/// * `id` - File id, also used as (unique) hash
+///
+/// # Returns
+///
+/// * File model
+pub fn file_model(id: &Uuid) -> entity::file::Model {
+    let now = Utc::now();
+
+    entity::file::Model {
+        id: id.as_bytes().to_vec(),
+        hash: id.to_string(),
+        uploader_ip: "127.0.0.1".into(),
+        uploaded_at: now.naive_utc(),
+        download_until: (now + Days::new(1)).naive_utc(),
+        encrypted_metadata: vec![],
+        successful_downloads: 0,
+        max_downloads: 1,
+        reservation_id: None,
+        reserved_until: None,
+        deletion_hash: None,
+        status_hash: None,
+        cipher: CipherId::default() as i16,
+        key_salt: None,
+        wrapped_key: None,
+        key_params: None,
+        client_encrypted: false,
+        pepper_id: None,
+        master_key_id: None,
+        wrapped_storage_key: None,
+        failed_attempts: 0,
+        plaintext_size: Some(100),
+        ciphertext_size: Some(132),
+    }
+}
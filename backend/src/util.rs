@@ -1,11 +1,85 @@
 //! Module with utilites that can't be categorized otherwise
 
 use crate::error::{Error, Result};
-use crate::hash::{Hash, Hashing};
-use base64::prelude::BASE64_URL_SAFE;
+use crate::hash::{pepper, Hash, Hashing};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::prelude::{BASE64_STANDARD, BASE64_URL_SAFE};
 use base64::Engine;
+use std::collections::HashSet;
+use std::time::Duration;
 
-/// Decodes and validates given file encryption key
+/// Length of generated tokens in bytes
+const TOKEN_LENGTH: usize = 32;
+/// Max delay between two retries, see [`retry_delay`]
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Generates a new random token, e.g. to authorize the deletion of a file
+///
+/// # Returns
+///
+/// * Random token
+pub fn generate_token() -> Vec<u8> {
+    let mut token = vec![0u8; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut token);
+    token
+}
+
+/// Calculates the delay before given `retry` with exponential backoff
+///
+/// # Arguments
+///
+/// * `initial` - Delay before the first retry
+/// * `retry` - Number of retries that have already been made
+///
+/// # Returns
+///
+/// * `initial` doubled `retry` times, but at most one minute
+pub fn retry_delay(initial: Duration, retry: u32) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(retry))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Parses numbered secrets from given `text`, e.g. peppers or master keys
+///
+/// Secrets are separated by commas or line breaks. Every secret consists of
+/// its id and its base64 encoded value, separated by a colon, e.g.
+/// `1:c2VjcmV0`. Ids must be positive and unique.
+///
+/// # Arguments
+///
+/// * `text` - Text to parse
+///
+/// # Returns
+///
+/// * [`Ok<Vec<(i16, Vec<u8>)>>`] on success, containing ids and secrets
+/// * [`Err<usize>`] containing the position of the first invalid entry,
+///   starting at 1. Entries aren't returned, as they contain secrets.
+pub fn parse_secrets(text: &str) -> std::result::Result<Vec<(i16, Vec<u8>)>, usize> {
+    let mut ids = HashSet::new();
+
+    text.split([',', '\n'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .enumerate()
+        .map(|(index, entry)| {
+            let position = index + 1;
+            let (id, secret) = entry.split_once(':').ok_or(position)?;
+            let id = id.trim().parse::<i16>().map_err(|_| position)?;
+            let secret = BASE64_STANDARD
+                .decode(secret.trim())
+                .map_err(|_| position)?;
+
+            if id <= 0 || secret.is_empty() || !ids.insert(id) {
+                return Err(position);
+            }
+
+            Ok((id, secret))
+        })
+        .collect()
+}
+
+/// Decodes and validates given file encryption key or token
 ///
 /// Given `encoded_key` is decoded and then checked against given `hash`.
 /// If the key is valid, it will be returned, otherwise error.
@@ -14,45 +88,129 @@ use base64::Engine;
 ///
 /// * `encoded_key` - File encryption key to decode and check
 /// * `hash` - Hash to check key against
+/// * `pepper_id` - Id of the pepper mixed into `hash`, if any
 ///
 /// # Returns
 ///
 /// * [`Ok<Vec<u8>>`] containing decoded and validated key  
 /// * [`Err<Error>`] on error
-pub fn get_validated_key(encoded_key: &str, hash: &str) -> Result<Vec<u8>> {
+pub async fn get_validated_key(
+    encoded_key: &str,
+    hash: &str,
+    pepper_id: Option<i16>,
+) -> Result<Vec<u8>> {
     let key = BASE64_URL_SAFE
         .decode(encoded_key)
         .map_err(|_| Error::KeyInvalid)?;
 
-    match Hash::verify(&key, hash).map_err(|_| Error::KeyInvalid) {
-        Ok(true) => Ok(key),
+    let pepper = match pepper_id {
+        None => None,
+        Some(pepper_id) => Some(pepper::get(pepper_id).ok_or_else(|| {
+            log::error!("Pepper {pepper_id} is not configured anymore");
+            Error::KeyInvalid
+        })?),
+    };
+
+    /* Verifying is CPU-bound, so it must not block the runtime */
+    let hash = hash.to_string();
+    let verification = tokio::task::spawn_blocking(move || {
+        Hash::verify(&key, &hash, pepper).map(|valid| valid.then_some(key))
+    })
+    .await;
+
+    match verification {
+        Ok(Ok(Some(key))) => Ok(key),
         _ => Err(Error::KeyInvalid),
     }
 }
 
+/// Rehashes given, already validated file encryption key if its `hash` has
+/// been created with weaker parameters than new hashes are or without the
+/// current pepper
+///
+/// # Arguments
+///
+/// * `encoded_key` - Validated file encryption key
+/// * `hash` - Current hash of the key
+/// * `pepper_id` - Id of the pepper mixed into `hash`, if any
+///
+/// # Returns
+///
+/// * [`Some<(String, Option<i16>)>`] containing the new hash and the id of
+///   its pepper, if key has been rehashed
+/// * [`None`] if hash is up to date or rehashing failed
+pub async fn rehash_key(
+    encoded_key: &str,
+    hash: &str,
+    pepper_id: Option<i16>,
+) -> Option<(String, Option<i16>)> {
+    let pepper = pepper::current();
+    let current_pepper_id = pepper.map(|pepper| pepper.id);
+
+    if !Hash::needs_rehash(hash) && pepper_id == current_pepper_id {
+        return None;
+    }
+
+    let key = BASE64_URL_SAFE.decode(encoded_key).ok()?;
+
+    /* Hashing is CPU-bound, so it must not block the runtime */
+    let rehashed = tokio::task::spawn_blocking(move || Hash::hash(&key, pepper))
+        .await
+        .map_err(|error| Error::HashingFailure(error.to_string()));
+
+    match rehashed {
+        Ok(Ok(hash)) => Some((hash, current_pepper_id)),
+        Ok(Err(error)) | Err(error) => {
+            log::error!("Could not rehash key: {error:?}");
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn valid_key_returned() {
+    #[tokio::test]
+    async fn valid_key_returned() {
         let result = get_validated_key(
             "MQ==", // "1"
             "$argon2id$v=19$m=12,t=3,p=1$dzc0OGd1OWZveHMwMDAwMA$c76OJ4RDh1TlW1tdcbimWA",
-        );
+            None,
+        )
+        .await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), [49]); // ["1"]
     }
 
-    #[test]
-    fn invalid_input_handled() {
-        assert!(get_validated_key("MQ==", "xxxYYY").is_err());
+    #[tokio::test]
+    async fn invalid_input_handled() {
+        assert!(get_validated_key("MQ==", "xxxYYY", None).await.is_err());
 
         assert!(get_validated_key(
             "@@@",
-            "$argon2id$v=19$m=12,t=3,p=1$dzc0OGd1OWZveHMwMDAwMA$c76OJ4RDh1TlW1tdcbimWA"
+            "$argon2id$v=19$m=12,t=3,p=1$dzc0OGd1OWZveHMwMDAwMA$c76OJ4RDh1TlW1tdcbimWA",
+            None
         )
+        .await
         .is_err());
     }
+
+    #[test]
+    fn invalid_secret_reported_by_position() {
+        assert_eq!(Err(1), parse_secrets("c2VjcmV0"));
+        assert_eq!(Err(2), parse_secrets("1:c2VjcmV0,\n1:c2VjcmV0"));
+        assert_eq!(Err(2), parse_secrets("1:c2VjcmV0, 0:c2VjcmV0"));
+    }
+
+    #[test]
+    fn retry_delay_doubled_up_to_max() {
+        let initial = Duration::from_secs(1);
+
+        assert_eq!(initial, retry_delay(initial, 0));
+        assert_eq!(Duration::from_secs(8), retry_delay(initial, 3));
+        assert_eq!(MAX_RETRY_DELAY, retry_delay(initial, 6));
+        assert_eq!(MAX_RETRY_DELAY, retry_delay(initial, u32::MAX));
+    }
 }